@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// How many bot posts are remembered. Oldest ones are forgotten first.
+const MAX_ANSWERS: usize = 2000;
+
+/// A post created by the bot.
+#[derive(Serialize, Deserialize)]
+struct Answer {
+    channel_id: String,
+    id: String,
+    /// post it answers, if any.
+    answered: String,
+}
+
+/// Posts created by the bot, linked to the posts they answer so they can be
+/// cleaned up after.
+///
+/// ```rust
+/// # use flobot_lib::answers::Answers;
+/// let mut answers = Answers::default();
+/// answers.record("channel", "joke", "command");
+/// answers.record("channel", "status", "");
+/// answers.record("other", "elsewhere", "");
+/// assert_eq!(vec!["joke"], answers.answers("command"));
+/// assert_eq!(vec!["status", "joke"], answers.recent("channel", 5));
+/// assert_eq!(vec!["status"], answers.recent("channel", 1));
+///
+/// answers.forget("joke");
+/// assert!(answers.answers("command").is_empty());
+/// assert_eq!(vec!["status"], answers.recent("channel", 5));
+///
+/// let answers = Answers::load(answers.save()).unwrap();
+/// assert_eq!(vec!["status"], answers.recent("channel", 5));
+/// ```
+#[derive(Default)]
+pub struct Answers {
+    posts: VecDeque<Answer>,
+    by_answered: HashMap<String, Vec<String>>,
+}
+
+impl Answers {
+    pub fn record(&mut self, channel_id: &str, id: &str, answered: &str) {
+        if !answered.is_empty() {
+            self.by_answered
+                .entry(answered.to_string())
+                .or_default()
+                .push(id.to_string());
+        }
+        self.posts.push_back(Answer {
+            channel_id: channel_id.to_string(),
+            id: id.to_string(),
+            answered: answered.to_string(),
+        });
+
+        while self.posts.len() > MAX_ANSWERS {
+            if let Some(oldest) = self.posts.pop_front() {
+                self.unlink(&oldest);
+            }
+        }
+    }
+
+    /// Posts remembered, to carry over to the next process on upgrade.
+    pub fn save(&self) -> serde_json::Value {
+        serde_json::to_value(&self.posts).unwrap_or_default()
+    }
+
+    /// Answers saved by the previous process.
+    pub fn load(saved: serde_json::Value) -> serde_json::Result<Self> {
+        let posts: Vec<Answer> = serde_json::from_value(saved)?;
+        let mut answers = Self::default();
+        for answer in posts.iter() {
+            answers.record(&answer.channel_id, &answer.id, &answer.answered);
+        }
+        Ok(answers)
+    }
+
+    fn unlink(&mut self, answer: &Answer) {
+        if let Some(ids) = self.by_answered.get_mut(&answer.answered) {
+            ids.retain(|id| id != &answer.id);
+            if ids.is_empty() {
+                self.by_answered.remove(&answer.answered);
+            }
+        }
+    }
+
+    /// Bot posts answering post_id.
+    pub fn answers(&self, post_id: &str) -> Vec<String> {
+        self.by_answered.get(post_id).cloned().unwrap_or_default()
+    }
+
+    /// Last n bot posts in channel_id, most recent first.
+    pub fn recent(&self, channel_id: &str, n: usize) -> Vec<String> {
+        self.posts
+            .iter()
+            .rev()
+            .filter(|a| a.channel_id == channel_id)
+            .take(n)
+            .map(|a| a.id.clone())
+            .collect()
+    }
+
+    /// Forget a deleted post.
+    pub fn forget(&mut self, post_id: &str) {
+        if let Some(pos) = self.posts.iter().position(|a| a.id == post_id) {
+            if let Some(answer) = self.posts.remove(pos) {
+                self.unlink(&answer);
+            }
+        }
+    }
+}
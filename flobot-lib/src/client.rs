@@ -1,5 +1,8 @@
 use crate::models::*;
+use crate::warn;
 use std::convert::From;
+use std::io::Read;
+use std::time::Duration;
 
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Error {
@@ -42,13 +45,113 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Send something to the backend.
 pub trait Sender {
     fn post(&self, post: &Post) -> Result<()>;
+    /// same as post, but returns the post as created by the backend, with its id.
+    fn create_post(&self, post: &Post) -> Result<Post>;
     fn reaction(&self, post: &Post, reaction: &str) -> Result<()>;
+    /// answer message to post, in its thread.
     fn reply(&self, post: &Post, message: &str) -> Result<()>;
+    /// upload files to the post channel and create post with them attached.
+    /// post.root_id, if any, makes the post an answer in that thread.
+    fn post_with_files(&self, post: &Post, files: &[File]) -> Result<Post>;
+    /// answer message to the author of post only. Backends unable to do so should
+    /// send it as a direct message.
+    fn ephemeral(&self, post: &Post, message: &str) -> Result<()>;
+    /// show the bot as typing in channel for a few seconds.
+    fn typing(&self, channel_id: &str) -> Result<()>;
+    /// delete a post.
+    fn delete(&self, post_id: &str) -> Result<()>;
+    /// download the file at url, to be sent with post_with_files.
+    fn download(&self, url: &str) -> Result<File> {
+        download(url)
+    }
+}
+
+/// Posts created by the bot, remembered so they can be cleaned up.
+pub trait Recall {
+    /// bot posts answering post_id, see Post::answered.
+    fn answers(&self, post_id: &str) -> Vec<String>;
+    /// last n bot posts in channel_id, most recent first.
+    fn recent(&self, channel_id: &str, n: usize) -> Vec<String>;
+}
+
+/// Status of the bot as shown to users.
+pub trait Presence {
+    fn online(&self) -> Result<()>;
+    fn away(&self) -> Result<()>;
+    /// text and emoji shown next to the bot name, until cleared.
+    fn custom_status(&self, emoji: &str, text: &str) -> Result<()>;
+    fn clear_custom_status(&self) -> Result<()>;
 }
 
 pub trait Editor {
     /// edit an existing post so it contains message instead.
     fn edit(&self, post: &Post, message: &str) -> Result<()>;
+    /// edit an existing post so it contains message and files instead.
+    fn edit_with_files(&self, post: &Post, message: &str, files: &[File])
+        -> Result<()>;
+}
+
+/// Largest file download accepts, in bytes.
+pub const MAX_DOWNLOAD: u64 = 20 * 1024 * 1024;
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Download the file at url, named after the last segment of its path. Urls come
+/// from users: files over MAX_DOWNLOAD and slow servers are errors.
+pub fn download(url: &str) -> Result<File> {
+    let res = reqwest::blocking::Client::builder()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()?
+        .error_for_status()?;
+    let too_big = || Error::Body(format!("{} is over {} bytes", url, MAX_DOWNLOAD));
+    if res.content_length().is_some_and(|len| len > MAX_DOWNLOAD) {
+        return Err(too_big());
+    }
+    let name = res
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("file")
+        .to_string();
+    let mut content = vec![];
+    res.take(MAX_DOWNLOAD + 1)
+        .read_to_end(&mut content)
+        .map_err(|e| Error::Other(e.to_string()))?;
+    if content.len() as u64 > MAX_DOWNLOAD {
+        return Err(too_big());
+    }
+    Ok(File::new(&name, content))
+}
+
+/// Post message as is, or the picture it links to if message is only an image url.
+/// Falls back to the url if the picture can't be downloaded.
+pub fn post_or_picture<S: Sender>(sender: &S, post: &Post) -> Result<()> {
+    if File::is_image_url(&post.message) {
+        match sender.download(post.message.trim()) {
+            Ok(file) => {
+                return sender
+                    .post_with_files(&post.nmessage(""), &[file])
+                    .map(|_| ())
+            }
+            Err(e) => warn!("cannot download {}: {:?}", post.message, e),
+        }
+    }
+    sender.post(post)
+}
+
+/// Whether the author of post is one of admins, by username.
+///
+/// The username is looked up from the post user_id: sender_name is chosen by
+/// the author and cannot be trusted.
+pub fn is_admin<G: Getter>(getter: &G, admins: &[String], post: &Post) -> bool {
+    match getter.users_by_ids(vec![&post.user_id]) {
+        Ok(users) => users
+            .first()
+            .is_some_and(|user| admins.iter().any(|admin| admin == &user.username)),
+        Err(_) => false,
+    }
 }
 
 pub trait Channel {
@@ -61,11 +164,33 @@ pub trait Channel {
     ) -> Result<String>;
     /// Archive given channel.
     fn archive(&self, channel_id: &str) -> Result<()>;
+    /// Opens, or reuses, the direct message channel between the bot and user_id and
+    /// returns its id.
+    fn direct(&self, user_id: &str) -> Result<String>;
 }
 
 pub trait Getter {
     fn my_user_id(&self) -> &str;
     fn users_by_ids(&self, ids: Vec<&str>) -> Result<Vec<User>>;
+    fn user_by_username(&self, username: &str) -> Result<Option<User>>;
+    fn channel_by_id(&self, channel_id: &str)
+        -> Result<Option<crate::models::Channel>>;
+    fn channel_by_name(
+        &self,
+        team_id: &str,
+        name: &str,
+    ) -> Result<Option<crate::models::Channel>>;
+    fn team_by_id(&self, team_id: &str) -> Result<Option<Team>>;
+    fn team_by_name(&self, name: &str) -> Result<Option<Team>>;
+    fn channel_members(&self, channel_id: &str) -> Result<Vec<User>>;
+    /// posts of the thread started by root_id, oldest first, root included.
+    fn thread(&self, root_id: &str) -> Result<Vec<Post>>;
+    /// whether name, without colons, is an emoji known by the server.
+    fn emoji_exists(&self, name: &str) -> Result<bool>;
+    /// every emoji name known by the server.
+    fn emoji_names(&self) -> Result<Vec<String>>;
+    /// url opening post_id in the chat, if posts have one.
+    fn permalink(&self, post_id: &str) -> Option<String>;
 }
 
 /// A Notifier implementation should only send messages to the debugging channel.
@@ -76,3 +201,55 @@ pub trait Notifier {
     fn error(&self, message: &str) -> Result<()>;
     fn required_action(&self, message: &str) -> Result<()>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve head then body once on a local port, return the url of /name.png.
+    fn serve(head: String, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/name.png", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+        url
+    }
+
+    #[test]
+    fn download_names_file_after_url() {
+        let head = "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\n";
+        let file = download(&serve(head.to_string(), b"png".to_vec())).unwrap();
+        assert_eq!("name.png", file.name);
+        assert_eq!(b"png".to_vec(), file.content);
+    }
+
+    #[test]
+    fn download_refuses_announced_big_files() {
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+            MAX_DOWNLOAD + 1
+        );
+        assert!(matches!(
+            download(&serve(head, vec![])),
+            Err(Error::Body(_))
+        ));
+    }
+
+    #[test]
+    fn download_stops_reading_big_files() {
+        let head = "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n";
+        let body = vec![0; MAX_DOWNLOAD as usize + 1];
+        assert!(matches!(
+            download(&serve(head.to_string(), body)),
+            Err(Error::Body(_))
+        ));
+    }
+}
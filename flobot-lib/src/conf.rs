@@ -2,6 +2,12 @@ use std::env::var;
 
 #[derive(Debug, Clone)]
 pub struct Conf {
+    /// name of the connection, used to tell servers apart when the bot manages more
+    /// than one of them.
+    pub name: String,
+    /// prefix applied to data stored for this connection so identifiers coming from
+    /// different servers can't collide. Empty for the single, unnamed connection.
+    pub namespace: String,
     /// a channel id/name/whatever is suitable for a given backend in order to publish
     /// debugging messages from the bot.
     /// this is used by Notifier trait implementations.
@@ -14,16 +20,100 @@ pub struct Conf {
     pub token: String,
     /// should you want to use a database to maintain states for the bot, use this variable.
     pub db_url: String,
+    /// handler names to load on this connection. None loads all of them.
+    pub handlers: Option<Vec<String>>,
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+fn secret(value: String) -> String {
+    crate::log::secret(&value);
+    value
+}
+
+/// Whether the variable key holds a credential.
+fn is_secret(key: &str) -> bool {
+    ["TOKEN", "KEY", "SECRET", "PASSWORD"]
+        .iter()
+        .any(|s| key.contains(s))
 }
 
 impl Conf {
+    /// Single connection configured from BOT_* variables.
     pub fn new() -> Result<Self, std::env::VarError> {
         Ok(Self {
+            name: "default".to_string(),
+            namespace: "".to_string(),
             debug_channel: var("BOT_DEBUG_CHAN").expect("BOT_DEBUG_CHAN"),
             api_url: var("BOT_API_URL").expect("BOT_API_URL"),
             ws_url: var("BOT_WS_URL").expect("BOT_WS_URL"),
-            token: var("BOT_TOKEN").expect("BOT_TOKEN"),
+            token: secret(var("BOT_TOKEN").expect("BOT_TOKEN")),
             db_url: var("BOT_DB_URL").expect("BOT_DB_URL"),
+            handlers: var("BOT_HANDLERS").ok().map(|h| list(&h)),
         })
     }
+
+    /// Connection named `name`, configured from BOT_<NAME>_* variables. The database
+    /// url falls back to BOT_DB_URL so connections can share the same database.
+    pub fn named(name: &str) -> Result<Self, std::env::VarError> {
+        let mut cfg = Self {
+            name: name.to_string(),
+            namespace: name.to_string(),
+            debug_channel: "".to_string(),
+            api_url: "".to_string(),
+            ws_url: "".to_string(),
+            token: "".to_string(),
+            db_url: "".to_string(),
+            handlers: None,
+        };
+
+        cfg.debug_channel = cfg.var("DEBUG_CHAN")?;
+        cfg.api_url = cfg.var("API_URL")?;
+        cfg.ws_url = cfg.var("WS_URL")?;
+        cfg.token = cfg.var("TOKEN")?;
+        cfg.db_url = cfg.var("DB_URL")?;
+        cfg.handlers = cfg.var("HANDLERS").ok().map(|h| list(&h));
+
+        Ok(cfg)
+    }
+
+    /// Load every connection listed in BOT_CONNECTIONS (comma separated names), or the
+    /// single connection from Conf::new if the variable is not set.
+    pub fn load_all() -> Result<Vec<Self>, std::env::VarError> {
+        match var("BOT_CONNECTIONS") {
+            Ok(names) => list(&names).iter().map(|name| Self::named(name)).collect(),
+            Err(_) => Ok(vec![Self::new()?]),
+        }
+    }
+
+    /// Read BOT_<NAME>_<KEY>, falling back to BOT_<KEY> so settings can be shared
+    /// between connections.
+    /// Values of keys looking like credentials are kept out of logs.
+    pub fn var(&self, key: &str) -> Result<String, std::env::VarError> {
+        let value = var(format!("BOT_{}_{}", self.name.to_uppercase(), key))
+            .or_else(|_| var(format!("BOT_{}", key)))?;
+        if is_secret(key) {
+            crate::log::secret(&value);
+        }
+        Ok(value)
+    }
+
+    /// Comma separated values of variable key, empty if not set.
+    pub fn var_list(&self, key: &str) -> Vec<String> {
+        self.var(key).map(|v| list(&v)).unwrap_or_default()
+    }
+
+    /// Whether the handler called `name` must be loaded on this connection.
+    pub fn handler_enabled(&self, name: &str) -> bool {
+        match &self.handlers {
+            Some(handlers) => handlers.iter().any(|h| h == name),
+            None => true,
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use crate::client;
+use crate::info;
 use crate::models::Post;
 use std::convert::From;
 
@@ -21,6 +22,31 @@ impl From<client::Error> for Error {
     }
 }
 
+impl Error {
+    /// Name of the variant, to group errors.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Database(_) => "Database",
+            Error::Timeout(_) => "Timeout",
+            Error::Status(_) => "Status",
+            Error::Other(_) => "Other",
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Database(e)
+            | Error::Timeout(e)
+            | Error::Status(e)
+            | Error::Other(e) => {
+                write!(f, "{}", e)
+            }
+        }
+    }
+}
+
 pub type Result = std::result::Result<(), Error>;
 
 /// Handle events after they have been through middleware.
@@ -31,6 +57,18 @@ pub trait Handler {
     fn name(&self) -> String;
     fn help(&self) -> Option<String>;
     fn handle(&self, data: &Self::Data) -> Result;
+    /// What the handler is up to, for state dumps, like a game in progress.
+    fn state(&self) -> Option<String> {
+        None
+    }
+    /// State to carry over to the next process on upgrade, if any.
+    fn save(&self) -> Option<serde_json::Value> {
+        None
+    }
+    /// Take over state saved by the previous process.
+    fn restore(&self, _saved: serde_json::Value) -> Result {
+        Ok(())
+    }
 }
 
 /// DO NOT USE IN PRODUCTION: Debug handler will PRINT ALL MESSAGES.
@@ -57,7 +95,7 @@ impl Handler for Debug {
     }
 
     fn handle(&self, post: &Post) -> Result {
-        println!("debug handler {:?} -> {:?}", self.name, post);
+        info!("debug handler {:?} -> {:?}", self.name, post);
         Ok(())
     }
 }
@@ -78,18 +116,43 @@ impl<PH> From<PH> for MutexedHandler<PH> {
     }
 }
 
+impl<PH> MutexedHandler<PH> {
+    /// A panic while handling poisons the mutex: the instance survives handler
+    /// panics, so the handler keeps serving.
+    fn lock(&self) -> std::sync::MutexGuard<'_, PH> {
+        self.handler.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
 impl<PH: Handler> Handler for MutexedHandler<PH> {
     type Data = PH::Data;
 
     fn name(&self) -> String {
-        self.handler.lock().unwrap().name()
+        self.lock().name()
     }
 
     fn help(&self) -> Option<String> {
-        self.handler.lock().unwrap().help()
+        self.lock().help()
     }
 
     fn handle(&self, data: &PH::Data) -> Result {
-        self.handler.lock().unwrap().handle(data)
+        self.lock().handle(data)
+    }
+
+    /// State dumps must not wait for a handler at work.
+    fn state(&self) -> Option<String> {
+        match self.handler.try_lock() {
+            Ok(handler) => handler.state(),
+            Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner().state(),
+            Err(std::sync::TryLockError::WouldBlock) => Some("busy".to_string()),
+        }
+    }
+
+    fn save(&self) -> Option<serde_json::Value> {
+        self.lock().save()
+    }
+
+    fn restore(&self, saved: serde_json::Value) -> Result {
+        self.lock().restore(saved)
     }
 }
@@ -0,0 +1,175 @@
+//! Liveness and readiness of the bot, from checks registered by its parts.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Ok when fine, the reason otherwise.
+pub type Check = Box<dyn Fn() -> Result<(), String> + Send + Sync>;
+
+/// Tells a loop is still making progress: it beats on each turn.
+#[derive(Clone)]
+pub struct Heartbeat {
+    last: Arc<Mutex<Instant>>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self {
+            last: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn beat(&self) {
+        *self.last.lock().unwrap() = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.last.lock().unwrap().elapsed()
+    }
+
+    /// Check failing once nothing beat for longer than max.
+    pub fn check(&self, max: Duration) -> Check {
+        let heartbeat = self.clone();
+        Box::new(move || {
+            let elapsed = heartbeat.elapsed();
+            if elapsed > max {
+                Err(format!("no progress for {} seconds", elapsed.as_secs()))
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+/// Result of every check, by name.
+pub struct Report {
+    results: Vec<(String, Result<(), String>)>,
+}
+
+impl Report {
+    pub fn ok(&self) -> bool {
+        self.results.iter().all(|(_, r)| r.is_ok())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, result) in self.results.iter() {
+            match result {
+                Ok(()) => writeln!(f, "ok {}", name)?,
+                Err(reason) => writeln!(f, "fail {}: {}", name, reason)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Health checks of the bot.
+///
+/// ```rust
+/// # use flobot_lib::health::{Health, Heartbeat};
+/// # use std::time::Duration;
+/// let heartbeat = Heartbeat::new();
+/// let mut health = Health::new();
+/// health.live("instance", heartbeat.check(Duration::from_secs(60)));
+/// health.ready("websocket", Box::new(|| Err("disconnected".to_string())));
+///
+/// assert!(health.live_report().ok());
+/// let ready = health.ready_report();
+/// assert!(!ready.ok());
+/// assert_eq!("ok instance\nfail websocket: disconnected\n", ready.to_string());
+/// ```
+#[derive(Default)]
+pub struct Health {
+    live: Vec<(String, Check)>,
+    ready: Vec<(String, Check)>,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check failing when the bot is hung and must be restarted.
+    pub fn live(&mut self, name: &str, check: Check) -> &mut Self {
+        self.live.push((name.to_string(), check));
+        self
+    }
+
+    /// Check failing when the bot cannot do its job for now, like while the
+    /// connection to the server is down.
+    pub fn ready(&mut self, name: &str, check: Check) -> &mut Self {
+        self.ready.push((name.to_string(), check));
+        self
+    }
+
+    pub fn live_report(&self) -> Report {
+        Report {
+            results: self.live.iter().map(|(n, c)| (n.clone(), c())).collect(),
+        }
+    }
+
+    /// A bot not alive is not ready either: liveness checks are included.
+    pub fn ready_report(&self) -> Report {
+        Report {
+            results: self
+                .live
+                .iter()
+                .chain(self.ready.iter())
+                .map(|(n, c)| (n.clone(), c()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_check_fails_without_beats() {
+        let heartbeat = Heartbeat::new();
+        let check = heartbeat.check(Duration::from_millis(20));
+        assert!(check().is_ok());
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(Err("no progress for 0 seconds".to_string()), check());
+        heartbeat.beat();
+        assert!(check().is_ok());
+    }
+
+    #[test]
+    fn heartbeat_clones_share_beats() {
+        let heartbeat = Heartbeat::new();
+        let check = heartbeat.clone().check(Duration::from_millis(20));
+        std::thread::sleep(Duration::from_millis(40));
+        heartbeat.beat();
+        assert!(check().is_ok());
+    }
+
+    #[test]
+    fn not_alive_is_not_ready() {
+        let mut health = Health::new();
+        health.live("instance", Box::new(|| Err("hung".to_string())));
+        health.ready("websocket", Box::new(|| Ok(())));
+
+        assert!(!health.live_report().ok());
+        let ready = health.ready_report();
+        assert!(!ready.ok());
+        assert_eq!("fail instance: hung\nok websocket\n", ready.to_string());
+    }
+
+    #[test]
+    fn no_checks_is_healthy() {
+        let health = Health::new();
+        assert!(health.live_report().ok());
+        assert!(health.ready_report().ok());
+        assert_eq!("", health.ready_report().to_string());
+    }
+}
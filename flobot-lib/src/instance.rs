@@ -1,12 +1,61 @@
 use crate::client;
 use crate::handler::Handler;
+use crate::health::Heartbeat;
+use crate::metrics::Metric;
 use crate::middleware::Continue;
 use crate::middleware::Error as MiddlewareError;
 use crate::middleware::Middleware as MMiddleware;
 use crate::models::{Event, Post, StatusCode, StatusError};
+use crate::panic::{self, Panic};
+use crate::report::Reporter;
+use crate::{debug, error, info, trace, warn};
 use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::From;
-use std::sync::mpsc::Receiver;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Handlers running longer than that show the bot as typing.
+const TYPING_AFTER: Duration = Duration::from_secs(1);
+/// Typing indicators fade out after a few seconds: send them again while busy.
+const TYPING_EVERY: Duration = Duration::from_secs(3);
+/// How often the typist looks for a slow handler.
+const TYPIST_TICK: Duration = Duration::from_millis(250);
+/// The instance beats at least that often while waiting for events.
+const IDLE_BEAT: Duration = Duration::from_secs(5);
+/// Handlers panicking that many times within PANIC_WINDOW are disabled.
+const MAX_PANICS: usize = 3;
+const PANIC_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// Errors repeating are summed up in a digest that often.
+const DIGEST_EVERY: Duration = Duration::from_secs(10 * 60);
+/// How often the digester looks for digests to post.
+const DIGEST_TICK: Duration = Duration::from_secs(1);
+/// Longest backtrace sent to the debug channel, in bytes.
+const MAX_BACKTRACE: usize = 4000;
+/// Events still queued that long after a shutdown are dropped.
+const DRAIN_FOR: Duration = Duration::from_secs(10);
+
+const EVENTS: Metric =
+    Metric::counter("flobot_events_received_total", "Events received, by type.");
+const HANDLER_CALLS: Metric =
+    Metric::counter("flobot_handler_calls_total", "Handler invocations.");
+const HANDLER_ERRORS: Metric = Metric::counter(
+    "flobot_handler_errors_total",
+    "Handler invocations that failed.",
+);
+const HANDLER_LATENCY: Metric =
+    Metric::histogram("flobot_handler_duration_seconds", "Time spent in handlers.");
+const MIDDLEWARE_DROPS: Metric = Metric::counter(
+    "flobot_middleware_drops_total",
+    "Events stopped by a middleware.",
+);
+const PANICS: Metric = Metric::counter(
+    "flobot_panics_total",
+    "Panics caught in handlers and middlewares.",
+);
 
 #[derive(Debug)]
 pub enum Error {
@@ -44,23 +93,194 @@ impl From<MiddlewareError> for Error {
 }
 
 pub type PostHandler = Box<dyn Handler<Data = Post> + Send + Sync>;
+/// EventHandler receives every event that went through middlewares, including posts.
+pub type EventHandler = Box<dyn Handler<Data = Event> + Send + Sync>;
 pub type Middleware = Box<dyn MMiddleware + Send + Sync>;
 
+/// A post handler currently running.
+struct Busy {
+    handler: String,
+    channel_id: String,
+    since: Instant,
+    typed: Option<Instant>,
+}
+
+/// Recent panics by handler name, and handlers disabled for panicking too often.
+#[derive(Default)]
+struct Panics {
+    recent: HashMap<String, Vec<Instant>>,
+    disabled: HashSet<String>,
+}
+
+impl Panics {
+    /// Record a panic of handler name, returns whether it got disabled.
+    fn record(&mut self, name: &str) -> bool {
+        let recent = self.recent.entry(name.to_string()).or_default();
+        recent.retain(|at| at.elapsed() < PANIC_WINDOW);
+        recent.push(Instant::now());
+        recent.len() >= MAX_PANICS && self.disabled.insert(name.to_string())
+    }
+
+    /// Returns whether handler name was disabled.
+    fn enable(&mut self, name: &str) -> bool {
+        self.recent.remove(name);
+        self.disabled.remove(name)
+    }
+}
+
 pub struct Instance<C> {
     middlewares: Vec<Middleware>,
     post_handlers: Vec<PostHandler>,
+    event_handlers: Vec<EventHandler>,
     helps: std::collections::HashMap<String, String>,
     client: C,
+    busy: Mutex<Option<Busy>>,
+    heartbeat: Heartbeat,
+    panics: Mutex<Panics>,
+    reporter: Mutex<Reporter>,
+    /// usernames allowed to enable disabled handlers.
+    admins: Vec<String>,
+    /// events waiting behind the one processed.
+    queued: AtomicUsize,
+    /// told on startup before the loaded handlers.
+    announce: String,
 }
 
-impl<C: client::Sender + client::Notifier> Instance<C> {
+impl<C: client::Sender + client::Notifier + client::Getter + Sync> Instance<C> {
     pub fn new(client: C) -> Self {
         Instance {
             middlewares: vec![],
             post_handlers: vec![],
+            event_handlers: vec![],
             helps: std::collections::HashMap::new(),
             client,
+            busy: Mutex::new(None),
+            heartbeat: Heartbeat::new(),
+            panics: Mutex::default(),
+            reporter: Mutex::new(Reporter::new(DIGEST_EVERY)),
+            admins: vec![],
+            queued: AtomicUsize::new(0),
+            announce: String::new(),
+        }
+    }
+
+    pub fn set_admins(&mut self, admins: Vec<String>) -> &mut Self {
+        self.admins = admins;
+        self
+    }
+
+    /// Beats while the instance receives events: it stops when a handler hangs or
+    /// the instance is gone.
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// Add message to the startup notification.
+    pub fn announce(&mut self, message: &str) -> &mut Self {
+        self.announce.push_str(message);
+        self
+    }
+
+    /// State of handlers to carry over to the next process on upgrade, by name.
+    pub fn save(&self) -> serde_json::Value {
+        let mut saved = serde_json::Map::new();
+        let post_saves = self.post_handlers.iter().map(|h| (h.name(), h.save()));
+        let event_saves = self.event_handlers.iter().map(|h| (h.name(), h.save()));
+        for (name, save) in post_saves.chain(event_saves) {
+            if let Some(save) = save {
+                saved.insert(name, save);
+            }
         }
+        serde_json::Value::Object(saved)
+    }
+
+    /// Give handlers the state saved by the previous process.
+    pub fn restore(&self, saved: serde_json::Value) {
+        let mut saved = match saved {
+            serde_json::Value::Object(saved) => saved,
+            _ => return,
+        };
+        let mut restored = vec![];
+        for handler in self.post_handlers.iter() {
+            if let Some(save) = saved.remove(&handler.name()) {
+                restored.push((handler.name(), handler.restore(save)));
+            }
+        }
+        for handler in self.event_handlers.iter() {
+            if let Some(save) = saved.remove(&handler.name()) {
+                restored.push((handler.name(), handler.restore(save)));
+            }
+        }
+        for (name, res) in restored {
+            match res {
+                Ok(()) => info!("handler {} restored", name),
+                Err(e) => warn!("handler {} cannot restore: {:?}", name, e),
+            }
+        }
+    }
+
+    /// Loaded middlewares and handlers, what they are up to and events waiting,
+    /// for state dumps.
+    ///
+    /// ```rust
+    /// # use flobot_lib::handler::Debug;
+    /// # use flobot_lib::instance::Instance;
+    /// # use flobot_lib::memory::Memory;
+    /// let mut instance = Instance::new(Memory::new("bot"));
+    /// instance.add_post_handler(Box::new(Debug::new("posts")));
+    /// let state = instance.state();
+    /// assert!(state.contains("post handlers: `debug`\n"), "{}", state);
+    /// assert!(state.contains("events queued: 0\n"), "{}", state);
+    /// ```
+    pub fn state(&self) -> String {
+        let disabled = self.panics.lock().unwrap().disabled.clone();
+        let names = |names: Vec<String>| -> String {
+            names
+                .into_iter()
+                .map(|name| {
+                    if disabled.contains(&name) {
+                        format!("`{}` (disabled)", name)
+                    } else {
+                        format!("`{}`", name)
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        let mut out = String::new();
+        let middlewares = self.middlewares.iter().map(|m| m.name().to_string());
+        out.push_str(&format!("middlewares: {}\n", names(middlewares.collect())));
+        let post_handlers = self.post_handlers.iter().map(|h| h.name());
+        out.push_str(&format!(
+            "post handlers: {}\n",
+            names(post_handlers.collect())
+        ));
+        let event_handlers = self.event_handlers.iter().map(|h| h.name());
+        out.push_str(&format!(
+            "event handlers: {}\n",
+            names(event_handlers.collect())
+        ));
+        out.push_str(&format!(
+            "events queued: {}\n",
+            self.queued.load(Ordering::SeqCst)
+        ));
+        if let Some(busy) = self.busy.lock().unwrap().as_ref() {
+            out.push_str(&format!(
+                "busy: `{}` for {} seconds\n",
+                busy.handler,
+                busy.since.elapsed().as_secs()
+            ));
+        }
+
+        let post_states = self.post_handlers.iter().map(|h| (h.name(), h.state()));
+        let event_states = self.event_handlers.iter().map(|h| (h.name(), h.state()));
+        for (name, state) in post_states.chain(event_states) {
+            if let Some(state) = state {
+                out.push_str(&format!(" * `{}`: {}\n", name, state));
+            }
+        }
+        out
     }
 
     pub fn add_middleware(&mut self, middleware: Middleware) -> &mut Self {
@@ -77,11 +297,84 @@ impl<C: client::Sender + client::Notifier> Instance<C> {
         self
     }
 
+    pub fn add_event_handler(&mut self, handler: EventHandler) -> &mut Self {
+        if let Some(help) = handler.help() {
+            self.helps.insert(handler.name(), help);
+        }
+        self.event_handlers.push(handler);
+        self
+    }
+
+    fn debug(&self, message: &str) {
+        if let Err(e) = self.client.debug(message) {
+            error!("debug error: {:?}", e);
+        }
+    }
+
+    /// Tell the debug channel about an error of handler, unless it already knows.
+    fn report(&self, handler: &str, e: crate::handler::Error, post: Option<&Post>) {
+        warn!("handler {} error: {:?}", handler, e);
+        let link = post
+            .filter(|post| !post.id.is_empty())
+            .and_then(|post| self.client.permalink(&post.id));
+        let message = self.reporter.lock().unwrap().error(
+            Instant::now(),
+            handler,
+            e.kind(),
+            &e.to_string(),
+            link,
+        );
+        if let Some(message) = message {
+            self.debug(&message);
+        }
+    }
+
+    /// Post digests of repeating errors until done.
+    fn digester(&self, done: &AtomicBool) {
+        while !done.load(Ordering::SeqCst) {
+            thread::sleep(DIGEST_TICK);
+            let digests = self.reporter.lock().unwrap().digest(Instant::now());
+            for digest in digests.iter() {
+                self.debug(digest);
+            }
+        }
+    }
+
+    /// Tell the debug channel what panicked, with the backtrace.
+    fn panicked(&self, what: &str, panic: Panic) {
+        PANICS.inc(&[("what", what)]);
+        error!("{} panicked: {}", what, panic.message);
+        let mut backtrace = panic.backtrace;
+        if backtrace.len() > MAX_BACKTRACE {
+            let mut end = MAX_BACKTRACE;
+            while !backtrace.is_char_boundary(end) {
+                end -= 1;
+            }
+            backtrace.truncate(end);
+            backtrace.push_str("\n…");
+        }
+        let message = format!("{} {}\n```\n{}\n```", what, panic.message, backtrace);
+        if let Err(e) = self.client.error(&message) {
+            error!("error notification error: {:?}", e);
+        }
+    }
+
     fn process_middlewares(&self, event: &mut Event) -> Result<Continue, Error> {
         for middleware in self.middlewares.iter() {
-            match middleware.process(event)? {
+            let res = match panic::catch(|| middleware.process(event)) {
+                Ok(res) => res?,
+                Err(p) => {
+                    // a middleware may filter events out: drop the event to be safe.
+                    self.panicked(&format!("middleware {}", middleware.name()), p);
+                    Continue::No
+                }
+            };
+            match res {
                 Continue::Yes => {}
-                Continue::No => return Ok(Continue::No),
+                Continue::No => {
+                    MIDDLEWARE_DROPS.inc(&[("middleware", middleware.name())]);
+                    return Ok(Continue::No);
+                }
             };
         }
 
@@ -97,7 +390,7 @@ impl<C: client::Sender + client::Notifier> Instance<C> {
                 reply.push_str(&format!("`{}`\n", key));
             }
 
-            return self.client.reply(post, &reply).map_err(client_err);
+            return self.client.ephemeral(post, &reply).map_err(client_err);
         }
 
         match Regex::new(r"^!help[\s]+([a-zA-Z0-9_-]+).*")
@@ -107,8 +400,8 @@ impl<C: client::Sender + client::Notifier> Instance<C> {
             Some(captures) => {
                 let name = captures.get(1).unwrap().as_str();
                 match self.helps.get(name) {
-                    Some(m) => self.client.reply(post, m),
-                    None => self.client.reply(post, "tutétrompé"),
+                    Some(m) => self.client.ephemeral(post, m),
+                    None => self.client.ephemeral(post, "tutétrompé"),
                 }
                 .map_err(client_err)
             }
@@ -116,34 +409,154 @@ impl<C: client::Sender + client::Notifier> Instance<C> {
         }
     }
 
+    /// Admins enable again handlers disabled after repeated panics.
+    fn process_enable(&self, post: &Post) -> Result<(), Error> {
+        let captures = match Regex::new(r"^!bot[\s]+enable[\s]+([a-zA-Z0-9_-]+)[\s]*$")
+            .unwrap()
+            .captures(&post.message)
+        {
+            Some(captures) => captures,
+            None => return Ok(()),
+        };
+        let name = captures.get(1).unwrap().as_str();
+
+        if !client::is_admin(&self.client, &self.admins, post) {
+            return self
+                .client
+                .reply(post, "réservé aux admins du bot.")
+                .map_err(client_err);
+        }
+
+        if self.panics.lock().unwrap().enable(name) {
+            info!("handler {} enabled again", name);
+            self.client.reaction(post, "ok_hand")
+        } else {
+            self.client
+                .reply(post, &format!("`{}` n'est pas désactivé.", name))
+        }
+        .map_err(client_err)
+    }
+
     fn process_event_post(&self, post: &Post) -> Result<(), Error> {
         let _ = self.process_help(post)?;
+        self.process_enable(post)?;
         for handler in self.post_handlers.iter() {
-            let res = handler.handle(post);
-            let _ = match res {
-                Ok(_) => {}
-                Err(e) => match self.client.debug(&format!("error: {:?}", e)) {
-                    Ok(_) => {}
-                    Err(e) => println!("debug error: {:?}", e),
-                },
-            };
+            let name = handler.name();
+            *self.busy.lock().unwrap() = Some(Busy {
+                handler: name.clone(),
+                channel_id: post.channel_id.clone(),
+                since: Instant::now(),
+                typed: None,
+            });
+            let res = self.call(&name, || handler.handle(post));
+            *self.busy.lock().unwrap() = None;
+            if let Some(Err(e)) = res {
+                self.report(&name, e, Some(post));
+            }
         }
         Ok(())
     }
 
+    /// Run a handler unless disabled, catching its panics. None when it did not
+    /// return.
+    fn call(
+        &self,
+        name: &str,
+        handle: impl FnOnce() -> crate::handler::Result,
+    ) -> Option<crate::handler::Result> {
+        if self.panics.lock().unwrap().disabled.contains(name) {
+            return None;
+        }
+
+        let panic = match panic::catch(|| self.measure(name, handle)) {
+            Ok(res) => return Some(res),
+            Err(panic) => panic,
+        };
+        self.panicked(&format!("handler {}", name), panic);
+        if self.panics.lock().unwrap().record(name) {
+            let message = format!(
+                "handler `{}` disabled after {} panics within an hour, `!bot enable {}` to enable it again",
+                name, MAX_PANICS, name
+            );
+            warn!("{}", message);
+            if let Err(e) = self.client.error(&message) {
+                error!("error notification error: {:?}", e);
+            }
+        }
+        None
+    }
+
+    /// Run a handler, counting its invocations, errors and time spent.
+    fn measure(
+        &self,
+        name: &str,
+        handle: impl FnOnce() -> crate::handler::Result,
+    ) -> crate::handler::Result {
+        let labels = [("handler", name)];
+        let start = Instant::now();
+        let res = handle();
+        HANDLER_LATENCY.observe(&labels, start.elapsed());
+        HANDLER_CALLS.inc(&labels);
+        if res.is_err() {
+            HANDLER_ERRORS.inc(&labels);
+        }
+        res
+    }
+
+    /// Show the bot as typing in the channel of a slow post handler, until done.
+    fn typist(&self, done: &AtomicBool) {
+        while !done.load(Ordering::SeqCst) {
+            thread::sleep(TYPIST_TICK);
+            let channel_id = match self.busy.lock().unwrap().as_mut() {
+                Some(busy)
+                    if busy.since.elapsed() >= TYPING_AFTER
+                        && busy.typed.is_none_or(|t| t.elapsed() >= TYPING_EVERY) =>
+                {
+                    busy.typed = Some(Instant::now());
+                    busy.channel_id.clone()
+                }
+                _ => continue,
+            };
+            if let Err(e) = self.client.typing(&channel_id) {
+                warn!("typing error: {:?}", e);
+            }
+        }
+    }
+
+    fn process_event_handlers(&self, event: &Event) {
+        let post = match event {
+            Event::Post(post) => Some(post),
+            _ => None,
+        };
+        for handler in self.event_handlers.iter() {
+            let name = handler.name();
+            if let Some(Err(e)) = self.call(&name, || handler.handle(event)) {
+                self.report(&name, e, post);
+            }
+        }
+    }
+
     fn process_event(&self, event: &Event) -> Result<(), Error> {
+        let res = self.process_event_kind(event);
+        self.process_event_handlers(event);
+        res
+    }
+
+    fn process_event_kind(&self, event: &Event) -> Result<(), Error> {
         match event {
             Event::Post(post) => self.process_event_post(post),
-            Event::PostEdited(_edited) => {
-                println!("edits are unsupported for now");
+            Event::PostEdited(_) | Event::PostDeleted(_) | Event::Reaction(_) => Ok(()),
+            Event::Action(action) => self.process_event_post(&action.as_post()),
+            Event::Reconnected => {
+                info!("reconnected to server");
                 Ok(())
             }
-            Event::Unsupported(_unsupported) => {
-                //println!("unsupported event: {:?}", unsupported);
+            Event::Unsupported(unsupported) => {
+                trace!("unsupported event: {:?}", unsupported);
                 Ok(())
             }
             Event::Hello(hello) => {
-                println!("hello server {:?}", hello.server_string);
+                info!("hello server {:?}", hello.server_string);
                 Ok(())
             }
             Event::Status(status) => match status.code {
@@ -157,7 +570,7 @@ impl<C: client::Sender + client::Notifier> Instance<C> {
                         .clone(),
                 )),
                 StatusCode::Unsupported => {
-                    println!("unsupported: {:?}", status);
+                    debug!("unsupported: {:?}", status);
                     Ok(())
                 }
                 StatusCode::Unknown => Err(Error::Other(
@@ -174,6 +587,7 @@ impl<C: client::Sender + client::Notifier> Instance<C> {
     }
 
     fn process(&self, event: &mut Event) -> Result<(), Error> {
+        EVENTS.inc(&[("type", event.kind())]);
         let res = self.process_middlewares(event)?;
         match res {
             Continue::Yes => self.process_event(event),
@@ -182,7 +596,8 @@ impl<C: client::Sender + client::Notifier> Instance<C> {
     }
 
     pub fn run(&self, receiver: Receiver<Event>) -> Result<(), Error> {
-        let mut loaded = String::from("## Loaded middlewares\n");
+        let mut loaded = self.announce.clone();
+        loaded.push_str("## Loaded middlewares\n");
         for m in self.middlewares.iter() {
             loaded.push_str(&format!(" * `{}`\n", m.name()));
         }
@@ -190,22 +605,191 @@ impl<C: client::Sender + client::Notifier> Instance<C> {
         for h in self.post_handlers.iter() {
             loaded.push_str(&format!(" * `{}`\n", h.name()));
         }
+        if !self.event_handlers.is_empty() {
+            loaded.push_str("## Loaded event handlers\n");
+            for h in self.event_handlers.iter() {
+                loaded.push_str(&format!(" * `{}`\n", h.name()));
+            }
+        }
 
         let _ = self.client.startup(&loaded)?;
 
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| self.typist(&done));
+            scope.spawn(|| self.digester(&done));
+            let res = self.receive(receiver);
+            done.store(true, Ordering::SeqCst);
+            res
+        })
+    }
+
+    /// Process event. An error is only reported: it must not stop the instance.
+    fn process_reported(&self, mut event: Event) {
+        if let Err(e) = self.process(&mut event) {
+            error!("process error: {:?}", e);
+            self.debug(&format!("error processing {} event: {:?}", event.kind(), e));
+        }
+    }
+
+    fn set_queued(&self, queued: usize) {
+        self.queued.store(queued, Ordering::SeqCst);
+    }
+
+    /// Process events queued before the shutdown, until deadline. Events left then
+    /// are dropped.
+    fn drain(
+        &self,
+        queue: &mut VecDeque<Event>,
+        receiver: &Receiver<Event>,
+        deadline: Instant,
+    ) {
+        let mut drained = 0;
+        queue.extend(receiver.try_iter());
+        while let Some(event) = queue.pop_front() {
+            self.set_queued(queue.len());
+            if let Event::Shutdown = event {
+                continue;
+            }
+            if Instant::now() >= deadline {
+                let dropped = 1 + queue
+                    .iter()
+                    .filter(|e| !matches!(e, Event::Shutdown))
+                    .count();
+                queue.clear();
+                self.set_queued(0);
+                warn!(
+                    "shutdown: {} queued events dropped, draining took too long",
+                    dropped
+                );
+                break;
+            }
+            self.process_reported(event);
+            drained += 1;
+            queue.extend(receiver.try_iter());
+        }
+        info!("shutdown: {} queued events processed", drained);
+    }
+
+    fn receive(&self, receiver: Receiver<Event>) -> Result<(), Error> {
+        // events are moved from the channel as they come, to know how many wait.
+        let mut queue = VecDeque::new();
         loop {
-            match receiver.recv() {
-                Ok(mut event) => match event {
-                    Event::Shutdown => return Ok(()),
-                    _ => self.process(&mut event)?,
-                },
-                Err(rte) => {
-                    return Err(Error::Consumer(format!(
-                        "receiving channel error: {}",
-                        rte.to_string()
-                    )))
+            self.heartbeat.beat();
+            if queue.is_empty() {
+                match receiver.recv_timeout(IDLE_BEAT) {
+                    Ok(event) => queue.push_back(event),
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(rte) => {
+                        return Err(Error::Consumer(format!(
+                            "receiving channel error: {}",
+                            rte.to_string()
+                        )))
+                    }
+                };
+            }
+            queue.extend(receiver.try_iter());
+            let event = queue.pop_front().unwrap();
+            self.set_queued(queue.len());
+            match event {
+                Event::Shutdown => {
+                    self.drain(&mut queue, &receiver, Instant::now() + DRAIN_FOR);
+                    return Ok(());
                 }
-            };
+                event => self.process_reported(event),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+
+    /// Counts posts handled.
+    struct Count(Arc<AtomicUsize>);
+
+    impl Handler for Count {
+        type Data = Post;
+
+        fn name(&self) -> String {
+            "count".into()
+        }
+        fn help(&self) -> Option<String> {
+            None
         }
+        fn handle(&self, _post: &Post) -> crate::handler::Result {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn counting() -> (Instance<Memory>, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut instance = Instance::new(Memory::new("bot"));
+        instance.add_post_handler(Box::new(Count(count.clone())));
+        (instance, count)
+    }
+
+    fn post() -> Event {
+        Event::Post(Post::with_message("hello"))
+    }
+
+    #[test]
+    fn run_processes_events_sent_before_shutdown() {
+        let (instance, count) = counting();
+        let (sender, receiver) = channel();
+        for event in [post(), Event::Shutdown, post()] {
+            sender.send(event).unwrap();
+        }
+        instance.run(receiver).unwrap();
+        assert_eq!(2, count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn drain_processes_queued_and_received_events() {
+        let (instance, count) = counting();
+        let (sender, receiver) = channel();
+        let mut queue = VecDeque::from(vec![post(), Event::Shutdown]);
+        sender.send(post()).unwrap();
+        instance.drain(&mut queue, &receiver, Instant::now() + DRAIN_FOR);
+        assert_eq!(2, count.load(Ordering::SeqCst));
+        assert_eq!(0, instance.queued.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn drain_drops_events_after_deadline() {
+        let (instance, count) = counting();
+        let (_sender, receiver) = channel();
+        let mut queue = VecDeque::from(vec![post(), post(), Event::Shutdown]);
+        instance.drain(&mut queue, &receiver, Instant::now());
+        assert_eq!(0, count.load(Ordering::SeqCst));
+        assert!(queue.is_empty());
+        assert_eq!(0, instance.queued.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn enable_checks_admins_by_user_id() {
+        let client = Memory::new("bot");
+        client.add_user("uadmin", "admin");
+        client.add_user("umallory", "mallory");
+        let mut instance = Instance::new(client.clone());
+        instance.set_admins(vec!["admin".to_string()]);
+
+        let mut post = Post::with_message("!bot enable count");
+        post.id = "command".to_string();
+        post.user_id = "umallory".to_string();
+        post.sender_name = "admin".to_string();
+        instance.process_enable(&post).unwrap();
+        post.user_id = "uadmin".to_string();
+        instance.process_enable(&post).unwrap();
+
+        let log = client.log();
+        assert_eq!(2, log.len());
+        assert!(log[0].ends_with("réservé aux admins du bot."));
+        assert!(log[1].ends_with("`count` n'est pas désactivé."));
     }
 }
@@ -1,9 +1,16 @@
+pub mod answers;
 pub mod client;
 pub mod conf;
 pub mod handler;
+pub mod health;
 pub mod instance;
+pub mod log;
+pub mod memory;
+pub mod metrics;
 pub mod middleware;
 pub mod models;
+pub mod panic;
+pub mod report;
 pub mod task;
 pub mod tempo;
 
@@ -0,0 +1,305 @@
+//! Leveled logging to stdout, filtered per module and scrubbed of secrets.
+//!
+//! Configured from BOT_LOG, a default level optionally followed by per module
+//! levels such as `info,flobot_mattermost::websocket=debug`, and BOT_LOG_FORMAT,
+//! `text` or `json`.
+
+use regex::Regex;
+use std::fmt;
+use std::sync::{OnceLock, RwLock};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(level: &str) -> Option<Self> {
+        match level.trim().to_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+/// Levels per module target, the longest matching prefix wins.
+///
+/// ```rust
+/// # use flobot_lib::log::{Filter, Level};
+/// let filter = Filter::parse("warn,flobot_mattermost=info,flobot_mattermost::websocket=debug");
+/// assert!(filter.enabled(Level::Warn, "flobot::joke"));
+/// assert!(!filter.enabled(Level::Info, "flobot::joke"));
+/// assert!(filter.enabled(Level::Info, "flobot_mattermost::client"));
+/// assert!(filter.enabled(Level::Debug, "flobot_mattermost::websocket"));
+/// assert!(!filter.enabled(Level::Trace, "flobot_mattermost::websocket"));
+/// ```
+pub struct Filter {
+    default: Level,
+    modules: Vec<(String, Level)>,
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Self {
+        let mut filter = Self {
+            default: Level::Info,
+            modules: vec![],
+        };
+        for part in spec.split(',').filter(|p| !p.trim().is_empty()) {
+            match part.split_once('=') {
+                Some((module, level)) => {
+                    if let Some(level) = Level::parse(level) {
+                        filter.modules.push((module.trim().to_string(), level));
+                    }
+                }
+                None => {
+                    if let Some(level) = Level::parse(part) {
+                        filter.default = level;
+                    }
+                }
+            }
+        }
+        filter
+            .modules
+            .sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        filter
+    }
+
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        let max = self
+            .modules
+            .iter()
+            .find(|(module, _)| target.starts_with(module.as_str()))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default);
+        level <= max
+    }
+}
+
+struct Logger {
+    filter: Filter,
+    json: bool,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| Logger {
+        filter: Filter::parse(&std::env::var("BOT_LOG").unwrap_or_default()),
+        json: std::env::var("BOT_LOG_FORMAT").is_ok_and(|f| f == "json"),
+    })
+}
+
+/// Read the configuration now instead of on the first log.
+pub fn init() {
+    logger();
+}
+
+/// Never log value: it is replaced wherever it appears. Short values are ignored,
+/// they would redact too much.
+pub fn secret(value: &str) {
+    let value = value.trim();
+    if value.len() < 6 {
+        return;
+    }
+    let mut secrets = SECRETS.write().unwrap();
+    if !secrets.iter().any(|s| s == value) {
+        secrets.push(value.to_string());
+    }
+}
+
+const REDACTED: &str = "<redacted>";
+
+fn patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        vec![
+            (
+                Regex::new(r"(?i)\b(bearer)\s+[\w.~+/=-]+").unwrap(),
+                "$1 <redacted>",
+            ),
+            (
+                Regex::new(
+                    r#"(?i)\b([\w-]*(?:token|api[_-]?key|secret|password))("?\s*[:=]\s*"?)[^\s",}]+"#,
+                )
+                .unwrap(),
+                "$1$2<redacted>",
+            ),
+            (
+                // international numbers of any country, French national ones.
+                Regex::new(r"(?:\+\d(?:[ .-]?\d){6,14}|\b0[1-9](?:[ .-]?\d{2}){4})\b")
+                    .unwrap(),
+                "<phone>",
+            ),
+        ]
+    })
+}
+
+/// Message without registered secrets, credentials and phone numbers.
+///
+/// ```rust
+/// # use flobot_lib::log::{redact, secret};
+/// secret("s3cr3t-value");
+/// assert_eq!("got <redacted>", redact("got s3cr3t-value"));
+/// assert_eq!(
+///     "pinterest access token: <redacted>",
+///     redact("pinterest access token: abc.def")
+/// );
+/// assert_eq!(r#"{"apikey":"<redacted>"}"#, redact(r#"{"apikey":"k3y"}"#));
+/// assert_eq!("Bearer <redacted>", redact("Bearer abc"));
+/// assert_eq!("token expired", redact("token expired"));
+/// assert_eq!("sms to <phone> sent", redact("sms to +33612345678 sent"));
+/// assert_eq!("sms to <phone>", redact("sms to 06 12 34 56 78"));
+/// assert_eq!("post at 1576937676623", redact("post at 1576937676623"));
+/// ```
+pub fn redact(message: &str) -> String {
+    let mut message = message.to_string();
+    for secret in SECRETS.read().unwrap().iter() {
+        message = message.replace(secret.as_str(), REDACTED);
+    }
+    for (re, replacement) in patterns().iter() {
+        message = re.replace_all(&message, *replacement).into_owned();
+    }
+    message
+}
+
+pub fn enabled(level: Level, target: &str) -> bool {
+    logger().filter.enabled(level, target)
+}
+
+/// Write a log line. Use the macros instead.
+pub fn log(level: Level, target: &str, args: fmt::Arguments) {
+    let logger = logger();
+    if !logger.filter.enabled(level, target) {
+        return;
+    }
+
+    let message = redact(&args.to_string());
+    let at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    if logger.json {
+        let line = serde_json::json!({
+            "at": at,
+            "level": level.as_str(),
+            "target": target,
+            "message": message,
+        });
+        println!("{}", line);
+    } else {
+        println!("{} {:5} {}: {}", at, level.as_str(), target, message);
+    }
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => ($crate::log::log($crate::log::Level::Error, module_path!(), format_args!($($arg)+)))
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => ($crate::log::log($crate::log::Level::Warn, module_path!(), format_args!($($arg)+)))
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => ($crate::log::log($crate::log::Level::Info, module_path!(), format_args!($($arg)+)))
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => ($crate::log::log($crate::log::Level::Debug, module_path!(), format_args!($($arg)+)))
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => ($crate::log::log($crate::log::Level::Trace, module_path!(), format_args!($($arg)+)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_defaults_to_info() {
+        let filter = Filter::parse("");
+        assert!(filter.enabled(Level::Info, "flobot"));
+        assert!(!filter.enabled(Level::Debug, "flobot"));
+    }
+
+    #[test]
+    fn filter_ignores_unknown_levels() {
+        let filter = Filter::parse("loud,flobot=chatty, flobot::joke = DEBUG ");
+        assert!(!filter.enabled(Level::Debug, "flobot::sms"));
+        assert!(filter.enabled(Level::Debug, "flobot::joke"));
+    }
+
+    #[test]
+    fn filter_longest_prefix_wins_in_any_order() {
+        let filter = Filter::parse("flobot::joke=trace,flobot=error");
+        assert!(filter.enabled(Level::Trace, "flobot::joke"));
+        assert!(!filter.enabled(Level::Warn, "flobot::sms"));
+    }
+
+    #[test]
+    fn short_secrets_are_not_redacted() {
+        secret(" abc ");
+        assert_eq!("abc", redact("abc"));
+    }
+
+    #[test]
+    fn secrets_are_redacted_everywhere() {
+        secret("hunter2-hunter2");
+        assert_eq!(
+            "<redacted> then <redacted>",
+            redact("hunter2-hunter2 then hunter2-hunter2")
+        );
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        assert_eq!(
+            "login?user=bot&password=<redacted> failed",
+            redact("login?user=bot&password=pa55 failed")
+        );
+        assert_eq!("api-key: <redacted>", redact("api-key: 1234"));
+        assert_eq!(
+            r#"{"client_secret": "<redacted>"}"#,
+            redact(r#"{"client_secret": "s"}"#)
+        );
+        assert_eq!(
+            "authorization: bearer <redacted>",
+            redact("authorization: bearer a.b")
+        );
+    }
+
+    #[test]
+    fn phone_numbers_are_redacted() {
+        assert_eq!("<phone>", redact("+336 12 34 56 78"));
+        assert_eq!("<phone>", redact("06.12.34.56.78"));
+        assert_eq!("<phone>", redact("06-12-34-56-78"));
+        assert_eq!("<phone>", redact("+14155552671"));
+        assert_eq!("<phone>", redact("+49 151 23456789"));
+        assert_eq!(
+            "!sms register bob <phone>",
+            redact("!sms register bob +32470123456")
+        );
+        assert_eq!("id 1234567890123", redact("id 1234567890123"));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::answers::Answers;
+use crate::client::*;
+use crate::models::{self, File, Post, Team, User};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// posts created, by id.
+    posts: HashMap<String, Post>,
+    users: HashMap<String, User>,
+    channels: HashMap<String, models::Channel>,
+    answers: Answers,
+    /// what the bot did, in order.
+    log: Vec<String>,
+}
+
+impl State {
+    fn id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{:06}", prefix, self.next_id)
+    }
+}
+
+/// Client keeping everything in memory: nothing is sent anywhere, what the bot
+/// does is logged instead. Used to replay recorded traffic and in tests.
+///
+/// ```rust
+/// # use flobot_lib::client::{Recall, Sender};
+/// # use flobot_lib::memory::Memory;
+/// # use flobot_lib::models::Post;
+/// let client = Memory::new("bot");
+/// let mut command = Post::with_message("!joke");
+/// command.id = "command".to_string();
+/// command.channel_id = "town".to_string();
+///
+/// client.post(&command.nmessage("une blague")).unwrap();
+/// client.reaction(&command, "ok_hand").unwrap();
+/// assert_eq!(
+///     vec!["post town: une blague", "reaction command: ok_hand"],
+///     client.log()
+/// );
+/// assert_eq!(1, client.answers("command").len());
+/// ```
+#[derive(Clone)]
+pub struct Memory {
+    me: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl Memory {
+    pub fn new(my_user_id: &str) -> Self {
+        Self {
+            me: my_user_id.to_string(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Make a user known to the client.
+    pub fn add_user(&self, id: &str, username: &str) {
+        self.state.lock().unwrap().users.insert(
+            id.to_string(),
+            User {
+                id: id.to_string(),
+                username: username.to_string(),
+                display_name: username.to_string(),
+            },
+        );
+    }
+
+    /// Everything the bot did so far.
+    pub fn log(&self) -> Vec<String> {
+        self.state.lock().unwrap().log.clone()
+    }
+
+    fn push(&self, entry: String) {
+        self.state.lock().unwrap().log.push(entry);
+    }
+
+    fn user(&self, id: &str) -> User {
+        match self.state.lock().unwrap().users.get(id) {
+            Some(user) => user.clone(),
+            None => User {
+                id: id.to_string(),
+                username: id.to_string(),
+                display_name: id.to_string(),
+            },
+        }
+    }
+}
+
+impl Sender for Memory {
+    fn post(&self, post: &Post) -> Result<()> {
+        self.create_post(post).map(|_| ())
+    }
+
+    fn create_post(&self, post: &Post) -> Result<Post> {
+        let mut state = self.state.lock().unwrap();
+        let mut created = post.clone();
+        created.id = state.id("post");
+        created.user_id = self.me.clone();
+        state
+            .answers
+            .record(&created.channel_id, &created.id, post.answered());
+        state.posts.insert(created.id.clone(), created.clone());
+        state
+            .log
+            .push(format!("post {}: {}", created.channel_id, created.message));
+        Ok(created)
+    }
+
+    fn reaction(&self, post: &Post, reaction: &str) -> Result<()> {
+        self.push(format!("reaction {}: {}", post.id, reaction));
+        Ok(())
+    }
+
+    fn reply(&self, post: &Post, message: &str) -> Result<()> {
+        self.post(&post.answer(message))
+    }
+
+    fn post_with_files(&self, post: &Post, files: &[File]) -> Result<Post> {
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        self.push(format!("files {}: {}", post.channel_id, names.join(", ")));
+        self.create_post(post)
+    }
+
+    fn ephemeral(&self, post: &Post, message: &str) -> Result<()> {
+        self.push(format!("ephemeral {}: {}", post.user_id, message));
+        Ok(())
+    }
+
+    fn typing(&self, _channel_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete(&self, post_id: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.posts.remove(post_id);
+        state.answers.forget(post_id);
+        state.log.push(format!("delete {}", post_id));
+        Ok(())
+    }
+
+    /// Nothing is fetched: the file is empty, named after the url.
+    fn download(&self, url: &str) -> Result<File> {
+        self.push(format!("download {}", url));
+        let name = url
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .unwrap_or("file");
+        Ok(File::new(name, vec![]))
+    }
+}
+
+impl Editor for Memory {
+    fn edit(&self, post: &Post, message: &str) -> Result<()> {
+        self.push(format!("edit {}: {}", post.id, message));
+        Ok(())
+    }
+
+    fn edit_with_files(
+        &self,
+        post: &Post,
+        message: &str,
+        files: &[File],
+    ) -> Result<()> {
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        self.push(format!("files {}: {}", post.id, names.join(", ")));
+        self.edit(post, message)
+    }
+}
+
+impl Channel for Memory {
+    fn create_private(
+        &self,
+        team_id: &str,
+        name: &str,
+        users: &Vec<String>,
+    ) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.id("channel");
+        state.channels.insert(
+            id.clone(),
+            models::Channel {
+                id: id.clone(),
+                team_id: team_id.to_string(),
+                name: name.to_string(),
+                display_name: name.to_string(),
+                kind: models::ChannelKind::Private,
+            },
+        );
+        state.log.push(format!(
+            "create private {} {}: {}",
+            id,
+            name,
+            users.join(", ")
+        ));
+        Ok(id)
+    }
+
+    fn archive(&self, channel_id: &str) -> Result<()> {
+        self.push(format!("archive {}", channel_id));
+        Ok(())
+    }
+
+    fn direct(&self, user_id: &str) -> Result<String> {
+        Ok(format!("direct-{}", user_id))
+    }
+}
+
+impl Getter for Memory {
+    fn my_user_id(&self) -> &str {
+        &self.me
+    }
+
+    fn users_by_ids(&self, ids: Vec<&str>) -> Result<Vec<User>> {
+        Ok(ids.iter().map(|id| self.user(id)).collect())
+    }
+
+    fn user_by_username(&self, username: &str) -> Result<Option<User>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .users
+            .values()
+            .find(|u| u.username == username)
+            .cloned())
+    }
+
+    fn channel_by_id(&self, channel_id: &str) -> Result<Option<models::Channel>> {
+        Ok(self.state.lock().unwrap().channels.get(channel_id).cloned())
+    }
+
+    fn channel_by_name(
+        &self,
+        team_id: &str,
+        name: &str,
+    ) -> Result<Option<models::Channel>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .channels
+            .values()
+            .find(|c| c.team_id == team_id && c.name == name)
+            .cloned())
+    }
+
+    fn team_by_id(&self, _team_id: &str) -> Result<Option<Team>> {
+        Ok(None)
+    }
+
+    fn team_by_name(&self, _name: &str) -> Result<Option<Team>> {
+        Ok(None)
+    }
+
+    fn channel_members(&self, _channel_id: &str) -> Result<Vec<User>> {
+        Ok(vec![])
+    }
+
+    fn thread(&self, root_id: &str) -> Result<Vec<Post>> {
+        let state = self.state.lock().unwrap();
+        let mut posts: Vec<Post> = state
+            .posts
+            .values()
+            .filter(|p| p.id == root_id || p.root_id == root_id)
+            .cloned()
+            .collect();
+        posts.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(posts)
+    }
+
+    fn emoji_exists(&self, _name: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn emoji_names(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    fn permalink(&self, _post_id: &str) -> Option<String> {
+        None
+    }
+}
+
+impl Notifier for Memory {
+    fn startup(&self, _message: &str) -> Result<()> {
+        self.push("startup".to_string());
+        Ok(())
+    }
+
+    fn debug(&self, message: &str) -> Result<()> {
+        self.push(format!("debug: {}", message));
+        Ok(())
+    }
+
+    fn error(&self, message: &str) -> Result<()> {
+        self.push(format!("error: {}", message));
+        Ok(())
+    }
+
+    fn required_action(&self, message: &str) -> Result<()> {
+        self.push(format!("required action: {}", message));
+        Ok(())
+    }
+}
+
+impl Presence for Memory {
+    fn online(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn away(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn custom_status(&self, emoji: &str, text: &str) -> Result<()> {
+        self.push(format!("status :{}: {}", emoji, text));
+        Ok(())
+    }
+
+    fn clear_custom_status(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Recall for Memory {
+    fn answers(&self, post_id: &str) -> Vec<String> {
+        self.state.lock().unwrap().answers.answers(post_id)
+    }
+
+    fn recent(&self, channel_id: &str, n: usize) -> Vec<String> {
+        self.state.lock().unwrap().answers.recent(channel_id, n)
+    }
+}
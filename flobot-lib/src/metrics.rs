@@ -0,0 +1,333 @@
+//! Process wide counters, gauges and histograms, rendered in the Prometheus text
+//! format.
+//!
+//! Metrics are declared as constants next to the code they measure, series are
+//! created on first use.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        }
+    }
+}
+
+pub type Labels<'a> = &'a [(&'a str, &'a str)];
+
+/// A metric family.
+///
+/// ```rust
+/// # use flobot_lib::metrics::{render, Metric};
+/// const CALLS: Metric = Metric::counter("calls_total", "Calls, by status.");
+/// CALLS.inc(&[("status", "200")]);
+/// CALLS.inc(&[("status", "200")]);
+/// CALLS.inc(&[("status", "error")]);
+///
+/// let text = render();
+/// assert!(text.contains("# TYPE calls_total counter\n"));
+/// assert!(text.contains("calls_total{status=\"200\"} 2\n"));
+/// assert!(text.contains("calls_total{status=\"error\"} 1\n"));
+/// ```
+pub struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: Kind,
+}
+
+impl Metric {
+    pub const fn counter(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: Kind::Counter,
+        }
+    }
+
+    pub const fn gauge(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: Kind::Gauge,
+        }
+    }
+
+    /// Histogram of durations, in seconds.
+    pub const fn histogram(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: Kind::Histogram,
+        }
+    }
+
+    pub fn inc(&self, labels: Labels) {
+        self.add(labels, 1.0)
+    }
+
+    pub fn add(&self, labels: Labels, value: f64) {
+        self.update(labels, |series| match series {
+            Series::Value(v) => *v += value,
+            Series::Histogram { .. } => {}
+        })
+    }
+
+    /// Only meaningful for gauges.
+    pub fn set(&self, labels: Labels, value: f64) {
+        self.update(labels, |series| {
+            if let Series::Value(v) = series {
+                *v = value
+            }
+        })
+    }
+
+    /// Only meaningful for histograms.
+    ///
+    /// ```rust
+    /// # use flobot_lib::metrics::{render, Metric};
+    /// # use std::time::Duration;
+    /// const LATENCY: Metric = Metric::histogram("latency_seconds", "Latency.");
+    /// LATENCY.observe(&[("handler", "joke")], Duration::from_millis(30));
+    ///
+    /// let text = render();
+    /// assert!(text.contains("latency_seconds_bucket{handler=\"joke\",le=\"0.025\"} 0\n"));
+    /// assert!(text.contains("latency_seconds_bucket{handler=\"joke\",le=\"0.05\"} 1\n"));
+    /// assert!(text.contains("latency_seconds_bucket{handler=\"joke\",le=\"+Inf\"} 1\n"));
+    /// assert!(text.contains("latency_seconds_count{handler=\"joke\"} 1\n"));
+    /// ```
+    pub fn observe(&self, labels: Labels, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        self.update(labels, |series| {
+            if let Series::Histogram {
+                buckets,
+                sum,
+                count,
+            } = series
+            {
+                for (bucket, le) in buckets.iter_mut().zip(BUCKETS.iter()) {
+                    if seconds <= *le {
+                        *bucket += 1;
+                    }
+                }
+                *sum += seconds;
+                *count += 1;
+            }
+        })
+    }
+
+    fn update(&self, labels: Labels, f: impl FnOnce(&mut Series)) {
+        let labels: Vec<(String, String)> = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut registry = REGISTRY.lock().unwrap();
+        let family = registry.entry(self.name).or_insert_with(|| Family {
+            help: self.help,
+            kind: self.kind,
+            series: BTreeMap::new(),
+        });
+        let kind = self.kind;
+        f(family
+            .series
+            .entry(labels)
+            .or_insert_with(|| Series::new(kind)));
+    }
+}
+
+enum Series {
+    Value(f64),
+    /// buckets are cumulative, as exposed.
+    Histogram {
+        buckets: [u64; BUCKETS.len()],
+        sum: f64,
+        count: u64,
+    },
+}
+
+impl Series {
+    fn new(kind: Kind) -> Self {
+        match kind {
+            Kind::Histogram => Series::Histogram {
+                buckets: [0; BUCKETS.len()],
+                sum: 0.0,
+                count: 0,
+            },
+            _ => Series::Value(0.0),
+        }
+    }
+}
+
+struct Family {
+    help: &'static str,
+    kind: Kind,
+    series: BTreeMap<Vec<(String, String)>, Series>,
+}
+
+static REGISTRY: Mutex<BTreeMap<&'static str, Family>> = Mutex::new(BTreeMap::new());
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_labels(labels: &[(String, String)], extra: Option<(&str, &str)>) -> String {
+    let mut all: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    if let Some((k, v)) = extra {
+        all.push(format!("{}=\"{}\"", k, v));
+    }
+    if all.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", all.join(","))
+    }
+}
+
+/// Every metric used so far, in the Prometheus text exposition format.
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap();
+    let mut out = String::new();
+    for (name, family) in registry.iter() {
+        let _ = writeln!(out, "# HELP {} {}", name, family.help);
+        let _ = writeln!(out, "# TYPE {} {}", name, family.kind.as_str());
+        for (labels, series) in family.series.iter() {
+            match series {
+                Series::Value(v) => {
+                    let _ =
+                        writeln!(out, "{}{} {}", name, format_labels(labels, None), v);
+                }
+                Series::Histogram {
+                    buckets,
+                    sum,
+                    count,
+                } => {
+                    for (bucket, le) in buckets.iter().zip(BUCKETS.iter()) {
+                        let le = le.to_string();
+                        let _ = writeln!(
+                            out,
+                            "{}_bucket{} {}",
+                            name,
+                            format_labels(labels, Some(("le", &le))),
+                            bucket
+                        );
+                    }
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{} {}",
+                        name,
+                        format_labels(labels, Some(("le", "+Inf"))),
+                        count
+                    );
+                    let _ = writeln!(
+                        out,
+                        "{}_sum{} {}",
+                        name,
+                        format_labels(labels, None),
+                        sum
+                    );
+                    let _ = writeln!(
+                        out,
+                        "{}_count{} {}",
+                        name,
+                        format_labels(labels, None),
+                        count
+                    );
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines of the rendered metric name, tests running in parallel share the
+    /// registry.
+    fn lines(name: &str) -> Vec<String> {
+        render()
+            .lines()
+            .filter(|l| l.starts_with(name))
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn bucket_bounds_are_inclusive() {
+        const BOUNDS: Metric = Metric::histogram("test_bounds_seconds", "Bounds.");
+        BOUNDS.observe(&[], Duration::from_millis(5));
+        let lines = lines("test_bounds_seconds_bucket");
+        assert_eq!("test_bounds_seconds_bucket{le=\"0.005\"} 1", lines[0]);
+    }
+
+    #[test]
+    fn buckets_are_cumulative() {
+        const CUMUL: Metric = Metric::histogram("test_cumul_seconds", "Cumulative.");
+        for millis in [1, 20, 300, 20_000] {
+            CUMUL.observe(&[], Duration::from_millis(millis));
+        }
+        let buckets: Vec<String> = lines("test_cumul_seconds_bucket")
+            .iter()
+            .map(|l| l.rsplit(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            vec!["1", "1", "2", "2", "2", "2", "3", "3", "3", "3", "3", "4"],
+            buckets
+        );
+        assert!(lines("test_cumul_seconds_sum")
+            .contains(&"test_cumul_seconds_sum 20.321".to_string()));
+        assert!(lines("test_cumul_seconds_count")
+            .contains(&"test_cumul_seconds_count 4".to_string()));
+    }
+
+    #[test]
+    fn histograms_ignore_add_and_set() {
+        const IGNORED: Metric = Metric::histogram("test_ignored_seconds", "Ignored.");
+        IGNORED.add(&[], 3.0);
+        IGNORED.set(&[], 3.0);
+        assert!(lines("test_ignored_seconds_count")
+            .contains(&"test_ignored_seconds_count 0".to_string()));
+    }
+
+    #[test]
+    fn gauges_are_set() {
+        const GAUGE: Metric = Metric::gauge("test_gauge", "Gauge.");
+        GAUGE.add(&[], 5.0);
+        GAUGE.set(&[], 2.0);
+        GAUGE.add(&[], 1.0);
+        assert_eq!(vec!["test_gauge 3"], lines("test_gauge "));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        const ESCAPED: Metric = Metric::counter("test_escaped_total", "Escaped.");
+        ESCAPED.inc(&[("name", "a\"b\\c\nd")]);
+        assert_eq!(
+            vec![r#"test_escaped_total{name="a\"b\\c\nd"} 1"#],
+            lines("test_escaped_total{")
+        );
+    }
+}
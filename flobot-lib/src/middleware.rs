@@ -1,4 +1,5 @@
 use crate::client;
+use crate::info;
 use crate::models::Event;
 use std::convert::From;
 
@@ -41,7 +42,7 @@ impl Debug {
 
 impl Middleware for Debug {
     fn process(&self, event: &mut Event) -> Result {
-        println!("middleware {:?} -> {:?}", self.name, event);
+        info!("middleware {:?} -> {:?}", self.name, event);
         Ok(Continue::Yes)
     }
 
@@ -5,9 +5,34 @@ pub enum Event {
     Status(Status),
     Unsupported(String),
     PostEdited(PostEdited),
+    PostDeleted(PostDeleted),
+    Reaction(Reaction),
+    /// a user clicked a button or picked a menu option of a post.
+    Action(Action),
+    /// the connection to the backend was lost and is back: events may have been
+    /// missed in between, handlers keeping remote state should resync.
+    Reconnected,
     Shutdown,
 }
 
+impl Event {
+    /// Short name of the event type, for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Hello(_) => "hello",
+            Event::Post(_) => "post",
+            Event::Status(_) => "status",
+            Event::Unsupported(_) => "unsupported",
+            Event::PostEdited(_) => "post_edited",
+            Event::PostDeleted(_) => "post_deleted",
+            Event::Reaction(_) => "reaction",
+            Event::Action(_) => "action",
+            Event::Reconnected => "reconnected",
+            Event::Shutdown => "shutdown",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Hello {
     pub server_string: String,
@@ -18,10 +43,20 @@ pub struct Post {
     pub channel_id: String,
     pub message: String,
     pub user_id: String,
+    /// first post of the thread this post belongs to. Empty outside of threads.
     pub root_id: String,
+    /// post this one answers to, in the thread. Empty outside of threads.
     pub parent_id: String,
     pub id: String,
     pub team_id: String,
+    /// kind of the channel the post was received on.
+    pub channel_kind: ChannelKind,
+    /// name of the author of a received post, as displayed by the server:
+    /// username, nickname or full name, or the name a webhook chose. Set by
+    /// the author, use user_id to identify them.
+    pub sender_name: String,
+    /// rich content displayed below the message. Only used when sending posts.
+    pub attachments: Vec<Attachment>,
 }
 
 #[derive(Clone, Debug)]
@@ -34,6 +69,145 @@ pub struct PostEdited {
     pub id: String,
 }
 
+#[derive(Clone, Debug)]
+pub struct PostDeleted {
+    pub channel_id: String,
+    pub user_id: String,
+    pub root_id: String,
+    pub id: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Reaction {
+    pub channel_id: String,
+    pub user_id: String,
+    pub post_id: String,
+    pub emoji_name: String,
+}
+
+/// Rich content displayed below a post message.
+#[derive(Clone, Debug, Default)]
+pub struct Attachment {
+    /// plain text summary, for clients unable to display attachments.
+    pub fallback: String,
+    /// hex color of the attachment border, like `#ff0000`.
+    pub color: String,
+    pub pretext: String,
+    pub title: String,
+    pub text: String,
+    pub fields: Vec<Field>,
+    pub controls: Vec<Control>,
+}
+
+/// A short title/value pair displayed in an attachment table.
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub title: String,
+    pub value: String,
+    /// whether the field is short enough to be displayed side by side with another.
+    pub short: bool,
+}
+
+/// Interactive part of an attachment. Using it sends an Event::Action carrying the
+/// chosen command, processed as if the user had posted the command.
+#[derive(Clone, Debug)]
+pub enum Control {
+    Button {
+        name: String,
+        command: String,
+    },
+    Select {
+        name: String,
+        options: Vec<SelectOption>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct SelectOption {
+    pub text: String,
+    pub command: String,
+}
+
+/// A user used a Control of a post.
+#[derive(Clone, Debug)]
+pub struct Action {
+    pub user_id: String,
+    pub channel_id: String,
+    pub team_id: String,
+    /// id of the post holding the control.
+    pub post_id: String,
+    pub command: String,
+}
+
+impl Attachment {
+    pub fn new(text: &str) -> Self {
+        Self {
+            fallback: text.to_string(),
+            text: text.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn field(mut self, title: &str, value: &str, short: bool) -> Self {
+        self.fields.push(Field {
+            title: title.to_string(),
+            value: value.to_string(),
+            short,
+        });
+        self
+    }
+
+    pub fn button(mut self, name: &str, command: &str) -> Self {
+        self.controls.push(Control::Button {
+            name: name.to_string(),
+            command: command.to_string(),
+        });
+        self
+    }
+
+    /// Add a menu, options being (text, command) pairs.
+    pub fn select(mut self, name: &str, options: &[(&str, &str)]) -> Self {
+        self.controls.push(Control::Select {
+            name: name.to_string(),
+            options: options
+                .iter()
+                .map(|(text, command)| SelectOption {
+                    text: text.to_string(),
+                    command: command.to_string(),
+                })
+                .collect(),
+        });
+        self
+    }
+}
+
+impl Action {
+    /// The command as posted by the user, on the post holding the control.
+    ///
+    /// ```rust
+    /// # use flobot_lib::models::Action;
+    /// let action = Action {
+    ///     user_id: "user".to_string(),
+    ///     channel_id: "channel".to_string(),
+    ///     team_id: "team".to_string(),
+    ///     post_id: "post".to_string(),
+    ///     command: "!ww join".to_string(),
+    /// };
+    /// let post = action.as_post();
+    /// assert_eq!("!ww join", post.message);
+    /// assert_eq!("user", post.user_id);
+    /// assert_eq!("post", post.id);
+    /// ```
+    pub fn as_post(&self) -> Post {
+        let mut post = Post::with_message(&self.command);
+        post.user_id = self.user_id.clone();
+        post.channel_id = self.channel_id.clone();
+        post.team_id = self.team_id.clone();
+        post.id = self.post_id.clone();
+        post
+    }
+}
+
 impl Post {
     pub fn new() -> Self {
         Self {
@@ -44,6 +218,9 @@ impl Post {
             parent_id: "".to_string(),
             id: "".to_string(),
             team_id: "".to_string(),
+            channel_kind: ChannelKind::Unknown,
+            sender_name: "".to_string(),
+            attachments: vec![],
         }
     }
 
@@ -59,11 +236,103 @@ impl Post {
         s
     }
 
+    /// Same post on channel id. Threads don't cross channels: on another channel,
+    /// the post is a new one outside of any thread, so its id, root_id and
+    /// parent_id are cleared and it no longer answers the original post. On the
+    /// same channel, it is left as is.
+    ///
+    /// ```rust
+    /// # use flobot_lib::models::Post;
+    /// let mut command = Post::with_message("!joke");
+    /// command.id = "command".to_string();
+    /// command.channel_id = "channel".to_string();
+    /// command.root_id = "root".to_string();
+    /// command.parent_id = "root".to_string();
+    ///
+    /// let same = command.nchannel("channel");
+    /// assert_eq!("command", same.id);
+    /// assert_eq!("root", same.root_id);
+    /// assert_eq!("root", same.parent_id);
+    ///
+    /// let elsewhere = command.nchannel("elsewhere");
+    /// assert_eq!("elsewhere", elsewhere.channel_id);
+    /// assert!(elsewhere.id.is_empty());
+    /// assert!(elsewhere.root_id.is_empty());
+    /// assert!(elsewhere.parent_id.is_empty());
+    /// assert_eq!("!joke", elsewhere.message);
+    /// ```
     pub fn nchannel(&self, id: &str) -> Self {
         let mut s = self.clone();
+        if s.channel_id != id {
+            s.id = "".to_string();
+            s.root_id = "".to_string();
+            s.parent_id = "".to_string();
+        }
         s.channel_id = id.to_string();
         s
     }
+
+    /// Root of the thread this post belongs to, or would start if answered.
+    pub fn thread_root(&self) -> &str {
+        if self.root_id.is_empty() {
+            &self.id
+        } else {
+            &self.root_id
+        }
+    }
+
+    /// A post answering this one with message, in its thread.
+    ///
+    /// ```rust
+    /// # use flobot_lib::models::Post;
+    /// let mut post = Post::with_message("question");
+    /// post.id = "question".to_string();
+    /// let answer = post.answer("answer");
+    /// assert_eq!("question", answer.root_id);
+    ///
+    /// let mut followup = Post::with_message("followup");
+    /// followup.id = "followup".to_string();
+    /// followup.root_id = "question".to_string();
+    /// let answer = followup.answer("answer");
+    /// assert_eq!("question", answer.root_id);
+    /// assert_eq!("followup", answer.parent_id);
+    /// ```
+    pub fn answer(&self, message: &str) -> Self {
+        let mut s = Self::with_message(message);
+        s.channel_id = self.channel_id.clone();
+        s.team_id = self.team_id.clone();
+        s.root_id = self.thread_root().to_string();
+        s.parent_id = self.id.clone();
+        s
+    }
+
+    /// Id of the received post this post, about to be sent, responds to: the post
+    /// it was copied from with nmessage, or the post it answers when made with
+    /// answer. Empty when it responds to none, as after nchannel to another
+    /// channel.
+    ///
+    /// ```rust
+    /// # use flobot_lib::models::Post;
+    /// let mut command = Post::with_message("!joke");
+    /// command.id = "command".to_string();
+    /// command.channel_id = "channel".to_string();
+    /// assert_eq!("command", command.nmessage("joke").answered());
+    /// assert_eq!("command", command.answer("joke").answered());
+    /// assert_eq!("", command.nchannel("elsewhere").answered());
+    /// ```
+    pub fn answered(&self) -> &str {
+        if self.id.is_empty() {
+            &self.parent_id
+        } else {
+            &self.id
+        }
+    }
+
+    pub fn nattachments(&self, attachments: Vec<Attachment>) -> Self {
+        let mut s = self.clone();
+        s.attachments = attachments;
+        s
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -88,12 +357,89 @@ pub struct StatusError {
     pub status_code: i32,
 }
 
+/// A file to attach to a post.
+#[derive(Clone, Debug)]
+pub struct File {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+impl File {
+    pub fn new(name: &str, content: Vec<u8>) -> Self {
+        Self {
+            name: name.to_string(),
+            content,
+        }
+    }
+
+    /// Whether text is nothing but a link to a picture, which reads better attached
+    /// as a file than as a bare url.
+    ///
+    /// ```rust
+    /// # use flobot_lib::models::File;
+    /// assert!(File::is_image_url("https://example.com/cat.GIF"));
+    /// assert!(File::is_image_url(" http://example.com/a/cat.png?size=2 "));
+    /// assert!(!File::is_image_url("look https://example.com/cat.png"));
+    /// assert!(!File::is_image_url("https://example.com/cat.html"));
+    /// ```
+    pub fn is_image_url(text: &str) -> bool {
+        let text = text.trim();
+        if !(text.starts_with("http://") || text.starts_with("https://"))
+            || text.contains(char::is_whitespace)
+        {
+            return false;
+        }
+
+        let path = text.split(['?', '#']).next().unwrap_or("");
+        let path = path.to_lowercase();
+        [".png", ".jpg", ".jpeg", ".gif", ".webp"]
+            .iter()
+            .any(|ext| path.ends_with(ext))
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct User {
     pub id: String,
     pub username: String,
     pub display_name: String,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelKind {
+    Public,
+    Private,
+    /// conversation between two users.
+    Direct,
+    /// conversation between a few users, outside of any team.
+    Group,
+    /// the backend didn't tell.
+    Unknown,
+}
+
+impl ChannelKind {
+    /// Whether the channel is a conversation outside of any team.
+    pub fn is_direct(&self) -> bool {
+        matches!(self, ChannelKind::Direct | ChannelKind::Group)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub id: String,
+    pub team_id: String,
+    pub name: String,
+    pub display_name: String,
+    pub kind: ChannelKind,
+}
+
+#[derive(Clone, Debug)]
+pub struct Team {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
 pub struct GenericMe {
     pub id: String,
 }
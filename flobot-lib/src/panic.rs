@@ -0,0 +1,107 @@
+//! Catch panics of a closure, with their message and backtrace.
+
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+/// A caught panic.
+#[derive(Debug)]
+pub struct Panic {
+    /// where and why it panicked.
+    pub message: String,
+    pub backtrace: String,
+}
+
+thread_local! {
+    /// set while catch runs a closure on this thread.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    static CAUGHT: RefCell<Option<Panic>> = const { RefCell::new(None) };
+}
+
+/// Panics caught are kept for catch instead of being printed. Others go to the
+/// previous hook.
+fn install_hook() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING.with(|c| c.get()) {
+                return previous(info);
+            }
+            let caught = Panic {
+                message: info.to_string(),
+                backtrace: Backtrace::force_capture().to_string(),
+            };
+            CAUGHT.with(|c| *c.borrow_mut() = Some(caught));
+        }));
+    });
+}
+
+/// Run f, turning a panic into an error.
+///
+/// ```rust
+/// # use flobot_lib::panic::catch;
+/// assert_eq!(Some(2), catch(|| 1 + 1).ok());
+///
+/// let items: Vec<u8> = vec![];
+/// let panic = catch(|| items[0]).unwrap_err();
+/// assert!(panic.message.contains("index out of bounds"));
+/// ```
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Panic> {
+    install_hook();
+    let catching = CATCHING.with(|c| c.replace(true));
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(catching));
+    res.map_err(|payload| {
+        CAUGHT
+            .with(|c| c.borrow_mut().take())
+            .unwrap_or_else(|| Panic {
+                message: payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string()),
+                backtrace: String::new(),
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatted_panics_keep_their_message() {
+        let caught = catch(|| panic!("handler {} failed", "joke")).unwrap_err();
+        assert!(
+            caught.message.contains("handler joke failed"),
+            "{}",
+            caught.message
+        );
+        assert!(caught.message.contains("panic.rs"), "{}", caught.message);
+        assert!(!caught.backtrace.is_empty());
+    }
+
+    #[test]
+    fn any_payload_is_caught() {
+        let caught = catch(|| std::panic::panic_any(42)).unwrap_err();
+        assert!(!caught.message.is_empty());
+    }
+
+    #[test]
+    fn nested_catches() {
+        let outer = catch(|| {
+            let inner = catch(|| panic!("inner"));
+            assert!(inner.unwrap_err().message.contains("inner"));
+            panic!("outer")
+        });
+        assert!(outer.unwrap_err().message.contains("outer"));
+    }
+
+    #[test]
+    fn catching_stops_after_catch() {
+        assert_eq!(Ok(1), catch(|| 1).map_err(|p| p.message));
+        assert!(!CATCHING.with(|c| c.get()));
+    }
+}
@@ -0,0 +1,193 @@
+//! Group handler errors so the debug channel is not flooded with identical
+//! messages.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Errors of a handler, of a kind.
+struct Group {
+    /// errors since the last message about the group.
+    count: u64,
+    message: String,
+    link: Option<String>,
+    /// when the group was last posted about.
+    posted: Instant,
+}
+
+/// The first error of a group is reported at once. Errors that follow are counted
+/// and summed up in a digest once per window. A group without any error for a
+/// whole window is resolved.
+///
+/// ```rust
+/// # use flobot_lib::report::Reporter;
+/// # use std::time::{Duration, Instant};
+/// let window = Duration::from_secs(600);
+/// let start = Instant::now();
+/// let mut reporter = Reporter::new(window);
+///
+/// let first = reporter.error(start, "joke", "Other", "api down", None);
+/// assert_eq!(Some("error in `joke` (Other): api down".to_string()), first);
+/// for _ in 0..42 {
+///     assert_eq!(None, reporter.error(start, "joke", "Other", "api down", None));
+/// }
+/// assert!(reporter.digest(start + Duration::from_secs(60)).is_empty());
+///
+/// assert_eq!(
+///     vec!["error in `joke` (Other): x42 in the last 10 min, last: api down"],
+///     reporter.digest(start + window)
+/// );
+/// assert_eq!(
+///     vec!["resolved `joke` (Other): no error in the last 10 min"],
+///     reporter.digest(start + window * 2)
+/// );
+/// assert!(reporter.digest(start + window * 3).is_empty());
+/// ```
+pub struct Reporter {
+    window: Duration,
+    groups: BTreeMap<(String, String), Group>,
+}
+
+fn with_link(message: String, link: &Option<String>) -> String {
+    match link {
+        Some(link) => format!("{}\n{}", message, link),
+        None => message,
+    }
+}
+
+impl Reporter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    /// Record an error of handler, link pointing to the post that triggered it.
+    /// Returns the message to post right away, if any.
+    pub fn error(
+        &mut self,
+        now: Instant,
+        handler: &str,
+        kind: &str,
+        message: &str,
+        link: Option<String>,
+    ) -> Option<String> {
+        let key = (handler.to_string(), kind.to_string());
+        if let Some(group) = self.groups.get_mut(&key) {
+            group.count += 1;
+            group.message = message.to_string();
+            group.link = link;
+            return None;
+        }
+
+        let posted = with_link(
+            format!("error in `{}` ({}): {}", handler, kind, message),
+            &link,
+        );
+        self.groups.insert(
+            key,
+            Group {
+                count: 0,
+                message: message.to_string(),
+                link,
+                posted: now,
+            },
+        );
+        Some(posted)
+    }
+
+    /// Messages summing up groups posted about a window ago or more.
+    pub fn digest(&mut self, now: Instant) -> Vec<String> {
+        let window = self.window;
+        let minutes = window.as_secs() / 60;
+        let mut messages = vec![];
+        self.groups.retain(|(handler, kind), group| {
+            if now.duration_since(group.posted) < window {
+                return true;
+            }
+            if group.count == 0 {
+                messages.push(format!(
+                    "resolved `{}` ({}): no error in the last {} min",
+                    handler, kind, minutes
+                ));
+                return false;
+            }
+            messages.push(with_link(
+                format!(
+                    "error in `{}` ({}): x{} in the last {} min, last: {}",
+                    handler, kind, group.count, minutes, group.message
+                ),
+                &group.link,
+            ));
+            group.count = 0;
+            group.posted = now;
+            true
+        });
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(600);
+
+    #[test]
+    fn groups_by_handler_and_kind() {
+        let now = Instant::now();
+        let mut reporter = Reporter::new(WINDOW);
+        assert!(reporter.error(now, "joke", "Other", "down", None).is_some());
+        assert!(reporter.error(now, "joke", "Db", "locked", None).is_some());
+        assert!(reporter.error(now, "sms", "Other", "down", None).is_some());
+        assert!(reporter.error(now, "joke", "Db", "locked", None).is_none());
+    }
+
+    #[test]
+    fn links_to_the_last_post() {
+        let now = Instant::now();
+        let mut reporter = Reporter::new(WINDOW);
+        let link = |id: &str| Some(format!("https://chat/pl/{}", id));
+        assert_eq!(
+            Some("error in `joke` (Other): down\nhttps://chat/pl/a".to_string()),
+            reporter.error(now, "joke", "Other", "down", link("a"))
+        );
+        reporter.error(now, "joke", "Other", "still down", link("b"));
+        assert_eq!(
+            vec!["error in `joke` (Other): x1 in the last 10 min, last: still down\nhttps://chat/pl/b"],
+            reporter.digest(now + WINDOW)
+        );
+    }
+
+    #[test]
+    fn errors_after_a_digest_wait_for_the_next_window() {
+        let start = Instant::now();
+        let mut reporter = Reporter::new(WINDOW);
+        reporter.error(start, "joke", "Other", "down", None);
+        reporter.error(start, "joke", "Other", "down", None);
+        assert_eq!(1, reporter.digest(start + WINDOW).len());
+
+        let later = start + WINDOW + Duration::from_secs(1);
+        assert!(reporter
+            .error(later, "joke", "Other", "down", None)
+            .is_none());
+        assert!(reporter.digest(later).is_empty());
+        assert_eq!(
+            vec!["error in `joke` (Other): x1 in the last 10 min, last: down"],
+            reporter.digest(start + WINDOW * 2)
+        );
+    }
+
+    #[test]
+    fn resolved_groups_are_reported_again() {
+        let start = Instant::now();
+        let mut reporter = Reporter::new(WINDOW);
+        reporter.error(start, "joke", "Other", "down", None);
+        assert_eq!(1, reporter.digest(start + WINDOW).len());
+
+        let later = start + WINDOW * 2;
+        assert!(reporter
+            .error(later, "joke", "Other", "down", None)
+            .is_some());
+    }
+}
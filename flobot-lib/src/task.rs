@@ -1,4 +1,7 @@
+use crate::health::Heartbeat;
+use crate::metrics::Metric;
 use crate::tempo::Tempo;
+use crate::{debug, error, info};
 use chrono::{self, DateTime, Duration as CDuration, Local};
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -10,6 +13,21 @@ pub fn cduration_from_secs(secs: u64) -> CDuration {
 
 pub type Now = DateTime<Local>;
 
+const RUNS: Metric = Metric::counter("flobot_task_runs_total", "Task executions.");
+const FAILURES: Metric =
+    Metric::counter("flobot_task_failures_total", "Task executions that failed.");
+const NEXT_RUN: Metric = Metric::gauge(
+    "flobot_task_next_run_timestamp_seconds",
+    "When the task runs next, as a unix timestamp.",
+);
+
+/// Schedule the task to run again in dur.
+fn schedule(tempo: &Tempo, key: String, dur: Duration) {
+    let at = Local::now() + CDuration::from_std(dur).unwrap();
+    NEXT_RUN.set(&[("task", &key)], at.timestamp() as f64);
+    tempo.set(key, dur);
+}
+
 #[derive(Debug, Clone)]
 pub enum Error {
     /// The task runner should reschedule the task as soon as possible.
@@ -42,6 +60,7 @@ pub struct SequentialTaskRunner {
     tasks: Vec<RunnableTask>,
     tempo: Tempo, // contain task names
     cont: Mutex<bool>,
+    heartbeat: Heartbeat,
 }
 
 impl SequentialTaskRunner {
@@ -50,7 +69,46 @@ impl SequentialTaskRunner {
             tasks: vec![],
             tempo: Tempo::new(),
             cont: Mutex::new(true), // TODO: use Arc<Mutex<bool>>?
+            heartbeat: Heartbeat::new(),
+        }
+    }
+
+    /// Beats on each run loop: it stops when a task hangs.
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// When each task runs next, for state dumps.
+    pub fn state(&self) -> String {
+        let mut out = String::new();
+        for task in self.tasks.iter() {
+            let key = task.name();
+            let next = match self.tempo.expires(&key) {
+                Some(at) => at.with_timezone(&Local).format("%F %T").to_string(),
+                None => "now".to_string(),
+            };
+            out.push_str(&format!(" * `{}`: next run {}\n", key, next));
+        }
+        out
+    }
+
+    /// Task schedule, to carry over to the next process on upgrade.
+    pub fn save(&self) -> String {
+        self.tempo.dump()
+    }
+
+    /// Take over the schedule saved by the previous process, so tasks do not all
+    /// run again at once.
+    pub fn restore(&self, saved: &str) -> Result<(), serde_json::Error> {
+        let saved = Tempo::load(saved)?;
+        for task in self.tasks.iter() {
+            let key = task.name();
+            if let Some(at) = saved.expires(&key) {
+                let dur = at.signed_duration_since(chrono::Utc::now());
+                schedule(&self.tempo, key, dur.to_std().unwrap_or_default());
+            }
         }
+        Ok(())
     }
 }
 
@@ -66,42 +124,41 @@ impl SequentialTaskRunner {
 impl TaskRunner for SequentialTaskRunner {
     fn add(&mut self, task: RunnableTask) -> Duration {
         let exec_in = task.init_exec(Local::now()).max(Duration::from_secs(3));
-        self.tempo.set(task.name(), exec_in);
+        schedule(&self.tempo, task.name(), exec_in);
         self.tasks.push(task);
         exec_in
     }
 
     fn run_forever(&self) {
         while *self.cont.lock().unwrap() {
+            self.heartbeat.beat();
             for task in self.tasks.iter() {
                 let key = task.name();
                 if self.tempo.exists(&key) {
                     continue; // skip task and run only when key is removed at future access.
                 }
+                let labels = [("task", key.as_str())];
+                RUNS.inc(&labels);
                 match task.exec(Local::now()) {
                     Err(e) => {
-                        println!("task {} failed: {:?}", key, e);
-                        match e {
-                            Error::Reschedule(_) => {
-                                self.tempo.set(key, Duration::from_secs(123))
-                            }
-                            Error::CannotExec((exec_in, _)) => {
-                                self.tempo.set(key, exec_in)
-                            }
-                            Error::ExpRetry(_) => {
-                                self.tempo.set(key, Duration::from_secs(196))
-                            } // TODO: implement exp
+                        FAILURES.inc(&labels);
+                        error!("task {} failed: {:?}", key, e);
+                        let dur = match e {
+                            Error::Reschedule(_) => Duration::from_secs(123),
+                            Error::CannotExec((exec_in, _)) => exec_in,
+                            Error::ExpRetry(_) => Duration::from_secs(196), // TODO: implement exp
                         };
+                        schedule(&self.tempo, key, dur);
                     }
                     Ok(rai) => {
                         let dur = rai.max(Duration::from_secs(60));
                         let at = Local::now() + CDuration::from_std(dur).unwrap();
-                        println!(
+                        info!(
                             "task {} next execution scheduled at {}",
                             task.name(),
                             at
                         );
-                        self.tempo.set(key, dur);
+                        schedule(&self.tempo, key, dur);
                     }
                 };
             }
@@ -123,7 +180,7 @@ impl Task for Tick {
     }
 
     fn exec(&self, _now: Now) -> Result<ExecIn, Error> {
-        println!("tick…");
+        debug!("tick…");
         Ok(Duration::from_secs(1))
     }
 
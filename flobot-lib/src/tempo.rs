@@ -87,9 +87,16 @@ impl<'de> Deserialize<'de> for Dt {
 /// assert_eq!(false, tempo.exists(&kexp));
 ///
 /// tempo.set(k1.clone(), Duration::from_secs(10));
+/// assert_eq!(1, tempo.count());
+/// assert!(tempo.expires(&k1).is_some());
+/// assert_eq!(None, tempo.expires(&kexp));
 /// let tdump = tempo.dump();
-/// let mut tempo_loaded = Tempo::load(&tdump);
-/// assert!(tempo.exists(&k1));
+/// let tempo_loaded = Tempo::load(&tdump).unwrap();
+/// assert!(tempo_loaded.exists(&k1));
+///
+/// let restored = Tempo::new();
+/// restored.restore(&tdump).unwrap();
+/// assert!(restored.exists(&k1));
 /// # }
 /// ```
 impl Tempo {
@@ -122,15 +129,81 @@ impl Tempo {
         };
     }
 
+    /// Keys not expired yet.
+    pub fn count(&self) -> usize {
+        let now = Utc::now();
+        let store = self.store.lock().unwrap();
+        store
+            .values()
+            .filter(|expire_in| expire_in.dt.gt(&now))
+            .count()
+    }
+
+    /// When key expires, unless it already did.
+    pub fn expires(&self, key: &str) -> Option<DateTime<Utc>> {
+        let store = self.store.lock().unwrap();
+        store
+            .get(key)
+            .map(|expire_in| expire_in.dt)
+            .filter(|dt| dt.gt(&Utc::now()))
+    }
+
     pub fn dump(&self) -> String {
         let store_guard = self.store.lock().unwrap();
         json!({"store": *store_guard}).to_string()
     }
 
+    /// Tempo with the keys of a dump.
     pub fn load(from: &str) -> Result<Self, serde_json::Error> {
-        let store: Store = serde_json::from_str(from)?;
-        Ok(Self {
-            store: Arc::new(Mutex::new(store)),
-        })
+        let tempo = Self::new();
+        tempo.restore(from)?;
+        Ok(tempo)
+    }
+
+    /// Replace keys with those of a dump. Clones share the restored keys.
+    pub fn restore(&self, from: &str) -> Result<(), serde_json::Error> {
+        let mut dumped: serde_json::Value = serde_json::from_str(from)?;
+        let store: Store = serde_json::from_value(dumped["store"].take())?;
+        *self.store.lock().unwrap() = store;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_skips_expired_keys() {
+        let tempo = Tempo::new();
+        tempo.set("kept".to_string(), Duration::from_secs(60));
+        tempo.set("expired".to_string(), Duration::from_secs(0));
+        assert_eq!(1, tempo.count());
+        assert!(tempo.expires("kept").is_some());
+        assert_eq!(None, tempo.expires("expired"));
+        assert_eq!(None, tempo.expires("unknown"));
+    }
+
+    #[test]
+    fn restore_replaces_keys_of_clones() {
+        let saved = Tempo::new();
+        saved.set("saved".to_string(), Duration::from_secs(60));
+
+        let tempo = Tempo::new();
+        let clone = tempo.clone();
+        tempo.set("replaced".to_string(), Duration::from_secs(60));
+        tempo.restore(&saved.dump()).unwrap();
+        assert!(clone.exists("saved"));
+        assert!(!clone.exists("replaced"));
+        assert_eq!(saved.expires("saved"), clone.expires("saved"));
+    }
+
+    #[test]
+    fn invalid_restore_keeps_keys() {
+        let tempo = Tempo::new();
+        tempo.set("kept".to_string(), Duration::from_secs(60));
+        assert!(tempo.restore("not json").is_err());
+        assert!(tempo.restore(r#"{"store": {"k": "yesterday"}}"#).is_err());
+        assert!(tempo.exists("kept"));
     }
 }
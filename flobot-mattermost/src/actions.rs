@@ -0,0 +1,159 @@
+use super::models::{
+    Action, ActionOption, ActionRequest, Attachment, Field, Integration, Props,
+};
+use flobot_lib::conf::Conf;
+use flobot_lib::models as gm;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Where the server sends interactive actions, and the secret proving an action
+/// comes from a post of the bot.
+#[derive(Clone)]
+pub struct Actions {
+    url: String,
+    secret: String,
+}
+
+impl Actions {
+    /// Read BOT_ACTIONS_URL, the base url the server can reach the bot http server
+    /// on. Without it, controls are displayed as commands to type.
+    pub(crate) fn from_conf(cfg: &Conf) -> Option<Self> {
+        let url = cfg.var("ACTIONS_URL").ok()?;
+        let secret = cfg
+            .var("ACTIONS_SECRET")
+            .unwrap_or_else(|_| Uuid::new_v4().to_simple().to_string());
+        Some(Self {
+            url: format!("{}/actions/{}", url.trim_end_matches('/'), cfg.name),
+            secret,
+        })
+    }
+
+    fn integration(&self, command: Option<&str>) -> Integration {
+        let mut context = HashMap::new();
+        context.insert("secret".to_string(), self.secret.clone());
+        if let Some(command) = command {
+            context.insert("command".to_string(), command.to_string());
+        }
+        Integration {
+            url: self.url.clone(),
+            context,
+        }
+    }
+
+    fn action(&self, id: usize, control: &gm::Control) -> Action {
+        match control {
+            gm::Control::Button { name, command } => Action {
+                id: format!("action{}", id),
+                name: name.clone(),
+                type_: "button",
+                options: vec![],
+                integration: self.integration(Some(command)),
+            },
+            gm::Control::Select { name, options } => Action {
+                id: format!("action{}", id),
+                name: name.clone(),
+                type_: "select",
+                options: options
+                    .iter()
+                    .map(|o| ActionOption {
+                        text: o.text.clone(),
+                        value: o.command.clone(),
+                    })
+                    .collect(),
+                // the chosen value comes back as the selected_option context.
+                integration: self.integration(None),
+            },
+        }
+    }
+}
+
+/// Controls as text, for servers that can't reach the bot.
+fn commands(controls: &[gm::Control]) -> String {
+    let mut text = String::new();
+    for control in controls.iter() {
+        match control {
+            gm::Control::Button { name, command } => {
+                text.push_str(&format!("\n * {} : `{}`", name, command))
+            }
+            gm::Control::Select { name, options } => {
+                text.push_str(&format!("\n * {} :", name));
+                for o in options.iter() {
+                    text.push_str(&format!("\n   * {} : `{}`", o.text, o.command));
+                }
+            }
+        }
+    }
+    text
+}
+
+impl super::client::Mattermost {
+    /// Whether controls of posted attachments are clickable.
+    pub fn actions_enabled(&self) -> bool {
+        self.actions.is_some()
+    }
+
+    pub(crate) fn props(&self, post: &gm::Post) -> Props {
+        let mut id = 0;
+        let attachments = post
+            .attachments
+            .iter()
+            .map(|a| {
+                let mut text = a.text.clone();
+                let actions = match &self.actions {
+                    Some(actions) => a
+                        .controls
+                        .iter()
+                        .map(|c| {
+                            id += 1;
+                            actions.action(id, c)
+                        })
+                        .collect(),
+                    None => {
+                        text.push_str(&commands(&a.controls));
+                        vec![]
+                    }
+                };
+                Attachment {
+                    fallback: a.fallback.clone(),
+                    color: a.color.clone(),
+                    pretext: a.pretext.clone(),
+                    title: a.title.clone(),
+                    text,
+                    fields: a
+                        .fields
+                        .iter()
+                        .map(|f| Field {
+                            title: f.title.clone(),
+                            value: f.value.clone(),
+                            short: f.short,
+                        })
+                        .collect(),
+                    actions,
+                }
+            })
+            .collect();
+
+        Props { attachments }
+    }
+
+    /// Decode an action request sent by the server to the integration url. None if
+    /// actions are disabled, the body is invalid or the secret doesn't match.
+    pub fn action(&self, body: &[u8]) -> Option<gm::Event> {
+        let actions = self.actions.as_ref()?;
+        let request: ActionRequest = serde_json::from_slice(body).ok()?;
+        let context = |key: &str| request.context.get(key).and_then(|v| v.as_str());
+
+        if context("secret") != Some(actions.secret.as_str()) {
+            return None;
+        }
+
+        let command = context("command").or_else(|| context("selected_option"))?;
+        Some(gm::Event::Action(gm::Action {
+            user_id: request.user_id.clone(),
+            channel_id: request.channel_id.clone(),
+            team_id: request.team_id.clone(),
+            post_id: request.post_id.clone(),
+            command: command.to_string(),
+        }))
+    }
+}
@@ -0,0 +1,114 @@
+use super::models::Stale;
+use flobot_lib::models as gm;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long looked up users, channels and teams are kept.
+const TTL: Duration = Duration::from_secs(600);
+
+/// A map forgetting its values after a while.
+///
+/// ```rust
+/// # use flobot_mattermost::cache::TtlMap;
+/// # use std::time::Duration;
+/// let map = TtlMap::new(Duration::from_secs(60));
+/// map.insert("key", 1);
+/// assert_eq!(Some(1), map.get(&"key"));
+/// map.remove(&"key");
+/// assert_eq!(None, map.get(&"key"));
+///
+/// let map = TtlMap::new(Duration::from_secs(0));
+/// map.insert("key", 1);
+/// assert_eq!(None, map.get(&"key"));
+/// ```
+pub struct TtlMap<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlMap<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((at, value)) if at.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        let ttl = self.ttl;
+        entries.retain(|_, (at, _)| at.elapsed() < ttl);
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    pub fn remove(&self, key: &K) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    /// Forget every value for which f returns false.
+    pub fn retain<F: Fn(&K, &V) -> bool>(&self, f: F) {
+        self.entries.lock().unwrap().retain(|k, (_, v)| f(k, v));
+    }
+}
+
+/// Cache of lookups made through the Getter implementation. Names are mapped to
+/// ids, so renaming only needs to drop the entry by id.
+pub struct Cache {
+    pub users: TtlMap<String, gm::User>,
+    pub usernames: TtlMap<String, String>,
+    pub channels: TtlMap<String, gm::Channel>,
+    /// (team id, channel name) to channel id.
+    pub channel_names: TtlMap<(String, String), String>,
+    pub teams: TtlMap<String, gm::Team>,
+    pub team_names: TtlMap<String, String>,
+    /// channel id to its members ids.
+    pub members: TtlMap<String, Vec<String>>,
+    /// names of the custom emoji of the server.
+    pub emoji: TtlMap<(), Vec<String>>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            users: TtlMap::new(TTL),
+            usernames: TtlMap::new(TTL),
+            channels: TtlMap::new(TTL),
+            channel_names: TtlMap::new(TTL),
+            teams: TtlMap::new(TTL),
+            team_names: TtlMap::new(TTL),
+            members: TtlMap::new(TTL),
+            emoji: TtlMap::new(TTL),
+        }
+    }
+}
+
+impl Cache {
+    pub fn invalidate(&self, stale: &Stale) {
+        match stale {
+            Stale::User(id) => {
+                self.users.remove(id);
+                self.usernames.retain(|_, user_id| user_id != id);
+            }
+            Stale::Channel(id) => {
+                self.channels.remove(id);
+                self.channel_names.retain(|_, channel_id| channel_id != id);
+            }
+            Stale::Members(channel_id) => self.members.remove(channel_id),
+            Stale::Emoji => self.emoji.remove(&()),
+        }
+    }
+}
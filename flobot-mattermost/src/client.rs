@@ -1,40 +1,191 @@
+use super::actions::Actions;
+use super::cache::Cache;
 use super::models::*;
-use flobot_lib::client::{Channel, Editor, Getter, Notifier, Result, Sender};
+use super::record::{Recorded, Recorder};
+use super::resume::Resume;
+use super::websocket::Link;
+use flobot_lib::answers::Answers;
+use flobot_lib::client::{
+    Channel, Editor, Getter, Notifier, Presence, Recall, Result, Sender,
+};
 use flobot_lib::conf::Conf;
+use flobot_lib::health::Heartbeat;
 use flobot_lib::models as gm;
+use flobot_lib::{info, warn};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct Mattermost {
     pub cfg: Conf,
     me: Me,
-    client: reqwest::blocking::Client,
+    pub(crate) client: reqwest::blocking::Client,
+    pub(crate) resume: Arc<Mutex<Resume>>,
+    /// set once the websocket is authenticated, until it is lost.
+    pub(crate) connected: Arc<AtomicBool>,
+    /// set by close: the websocket is not reconnected anymore.
+    pub(crate) closing: Arc<AtomicBool>,
+    /// beats on each frame received from the websocket.
+    pub(crate) heartbeat: Heartbeat,
+    pub(crate) actions: Option<Actions>,
+    pub(crate) cache: Arc<Cache>,
+    pub(crate) link: Arc<Mutex<Link>>,
+    answers: Arc<Mutex<Answers>>,
+    pub(crate) recorder: Option<Recorder>,
 }
 
 impl Mattermost {
     pub fn new(cfg: Conf) -> Result<Self> {
-        let client = reqwest::blocking::Client::new();
-        let me: Me = client
-            .get(&format!("{}/users/me", &cfg.api_url))
-            .bearer_auth(&cfg.token)
-            .send()?
-            .json()?;
-        println!("my user id: {}", me.id);
-        Ok(Mattermost {
+        let mut mm = Mattermost {
+            actions: Actions::from_conf(&cfg),
+            recorder: Recorder::from_conf(&cfg),
             cfg: cfg,
-            me,
-            client,
-        })
+            me: Me::default(),
+            client: reqwest::blocking::Client::new(),
+            resume: Arc::default(),
+            connected: Arc::default(),
+            closing: Arc::default(),
+            heartbeat: Heartbeat::new(),
+            cache: Arc::default(),
+            link: Arc::default(),
+            answers: Arc::default(),
+        };
+        mm.me = mm
+            .client
+            .get(mm.url("/users/me"))
+            .bearer_auth(&mm.cfg.token)
+            .send_recorded(&mm)?
+            .json()?;
+        info!("my user id: {}", mm.me.id);
+        Ok(mm)
     }
 
-    fn url(&self, add: &str) -> String {
+    pub(crate) fn url(&self, add: &str) -> String {
         let mut url = self.cfg.api_url.clone();
         url.push_str(add);
         url
     }
+
+    /// Mattermost version of post, in the thread of post.root_id if any.
+    fn new_post<'a>(&self, post: &'a gm::Post, file_ids: Vec<String>) -> NewPost<'a> {
+        let root_id = match post.root_id.as_str() {
+            "" => None,
+            root_id => Some(root_id.to_string()),
+        };
+        NewPost {
+            channel_id: post.channel_id.clone(),
+            create_at: 0,
+            file_ids,
+            message: &post.message,
+            metadata: Metadata {},
+            props: self.props(post),
+            update_at: 0,
+            user_id: self.me.id.clone(),
+            // the server only knows about threads: answers are attached to the root.
+            parent_id: root_id.clone(),
+            root_id,
+        }
+    }
+
+    /// GET path, None if not found.
+    fn get_opt<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<Option<T>> {
+        let res = self
+            .client
+            .get(self.url(path))
+            .bearer_auth(&self.cfg.token)
+            .send_recorded(self)?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(res.error_for_status()?.json()?))
+    }
+
+    /// Names of the custom emoji of the server, none when they are disabled.
+    fn custom_emoji(&self) -> Result<Vec<String>> {
+        if let Some(names) = self.cache.emoji.get(&()) {
+            return Ok(names);
+        }
+
+        let per_page = 200;
+        let mut names = vec![];
+        for page in 0.. {
+            let res = self
+                .client
+                .get(self.url("/emoji"))
+                .query(&[("page", page), ("per_page", per_page)])
+                .bearer_auth(&self.cfg.token)
+                .send_recorded(self)?;
+            if res.status() == reqwest::StatusCode::NOT_IMPLEMENTED {
+                break;
+            }
+            let emoji: Vec<Emoji> = res.error_for_status()?.json()?;
+            let count = emoji.len();
+            names.extend(emoji.into_iter().map(|e| e.name));
+            if count < per_page {
+                break;
+            }
+        }
+        self.cache.emoji.insert((), names.clone());
+        Ok(names)
+    }
+
+    fn cache_channel(
+        &self,
+        channel: Option<super::models::Channel>,
+    ) -> Option<gm::Channel> {
+        let channel: gm::Channel = channel?.into();
+        self.cache.channel_names.insert(
+            (channel.team_id.clone(), channel.name.clone()),
+            channel.id.clone(),
+        );
+        self.cache
+            .channels
+            .insert(channel.id.clone(), channel.clone());
+        Some(channel)
+    }
+
+    fn cache_team(&self, team: Option<Team>) -> Option<gm::Team> {
+        let team: gm::Team = team?.into();
+        self.cache
+            .team_names
+            .insert(team.name.clone(), team.id.clone());
+        self.cache.teams.insert(team.id.clone(), team.clone());
+        Some(team)
+    }
+
+    /// Upload files to channel_id and return their ids, to be attached to a post.
+    fn upload(&self, channel_id: &str, files: &[gm::File]) -> Result<Vec<String>> {
+        let mut ids = vec![];
+        for file in files.iter() {
+            let uploads: FileUploads = self
+                .client
+                .post(self.url("/files"))
+                .query(&[("channel_id", channel_id), ("filename", &file.name)])
+                .bearer_auth(&self.cfg.token)
+                .body(file.content.clone())
+                .send_recorded(self)?
+                .error_for_status()?
+                .json()?;
+            ids.extend(uploads.file_infos.into_iter().map(|info| info.id));
+        }
+        Ok(ids)
+    }
 }
 
 impl Channel for Mattermost {
+    fn direct(&self, user_id: &str) -> Result<String> {
+        let channel: GenericID = self
+            .client
+            .post(self.url("/channels/direct"))
+            .bearer_auth(&self.cfg.token)
+            .json(&[self.me.id.as_str(), user_id])
+            .send_recorded(self)?
+            .error_for_status()?
+            .json()?;
+        Ok(channel.id)
+    }
+
     fn create_private(
         &self,
         team_id: &str,
@@ -56,7 +207,7 @@ impl Channel for Mattermost {
             .post(&self.url("/channels"))
             .bearer_auth(&self.cfg.token)
             .json(&mmchannel)
-            .send()?
+            .send_recorded(self)?
             .json()?;
 
         for user_id in users.iter() {
@@ -67,7 +218,7 @@ impl Channel for Mattermost {
                 .post(&self.url(&format!("/channels/{}/members", r.id)))
                 .bearer_auth(&self.cfg.token)
                 .json(&uid)
-                .send()?;
+                .send_recorded(self)?;
         }
 
         Ok(r.id)
@@ -77,7 +228,7 @@ impl Channel for Mattermost {
         self.client
             .delete(&self.url(&format!("/channels/{}", channel_id)))
             .bearer_auth(&self.cfg.token)
-            .send()?;
+            .send_recorded(self)?;
 
         Ok(())
     }
@@ -85,24 +236,20 @@ impl Channel for Mattermost {
 
 impl Sender for Mattermost {
     fn post(&self, post: &gm::Post) -> Result<()> {
-        let mmpost = NewPost {
-            channel_id: post.channel_id.clone(),
-            create_at: 0,
-            file_ids: vec![],
-            message: &post.message,
-            metadata: Metadata {},
-            props: Props {},
-            update_at: 0,
-            user_id: self.me.id.clone(),
-            parent_id: None,
-            root_id: None,
-        };
-        self.client
-            .post(&self.url("/posts"))
+        self.create_post(post).map(|_| ())
+    }
+
+    fn create_post(&self, post: &gm::Post) -> Result<gm::Post> {
+        let created: Post = self
+            .client
+            .post(self.url("/posts"))
             .bearer_auth(&self.cfg.token)
-            .json(&mmpost)
-            .send()?;
-        Ok(())
+            .json(&self.new_post(post, vec![]))
+            .send_recorded(self)?
+            .error_for_status()?
+            .json()?;
+        self.remember(post, &created);
+        Ok(created.into())
     }
 
     fn reaction(&self, post: &gm::Post, reaction: &str) -> Result<()> {
@@ -115,28 +262,141 @@ impl Sender for Mattermost {
             .post(&self.url("/reactions"))
             .bearer_auth(&self.cfg.token)
             .json(&reaction)
-            .send()?;
+            .send_recorded(self)?;
         Ok(())
     }
 
     fn reply(&self, post: &gm::Post, message: &str) -> Result<()> {
-        let mmpost = NewPost {
-            channel_id: post.channel_id.clone(),
-            create_at: 0,
-            file_ids: vec![],
-            message: message,
-            metadata: Metadata {},
-            props: Props {},
-            update_at: 0,
-            user_id: self.me.id.clone(),
-            parent_id: Some(post.id.clone()),
-            root_id: Some(post.id.clone()),
+        self.post(&post.answer(message))
+    }
+
+    fn ephemeral(&self, post: &gm::Post, message: &str) -> Result<()> {
+        // stay in the thread of post, if any, without starting a new one.
+        let answer = post.nmessage(message).nattachments(vec![]);
+        let ephemeral = EphemeralPost {
+            user_id: &post.user_id,
+            post: self.new_post(&answer, vec![]),
+        };
+        let res = self
+            .client
+            .post(self.url("/posts/ephemeral"))
+            .bearer_auth(&self.cfg.token)
+            .json(&ephemeral)
+            .send_recorded(self)
+            .and_then(|r| r.error_for_status());
+
+        match res {
+            Ok(_) => Ok(()),
+            // missing permission or disabled on the server: answer privately.
+            Err(e) => {
+                warn!("ephemeral post failed, sending a direct message: {:?}", e);
+                let channel_id = self.direct(&post.user_id)?;
+                self.post(&gm::Post::with_message(message).nchannel(&channel_id))
+            }
+        }
+    }
+
+    fn post_with_files(&self, post: &gm::Post, files: &[gm::File]) -> Result<gm::Post> {
+        let file_ids = self.upload(&post.channel_id, files)?;
+        let created: Post = self
+            .client
+            .post(self.url("/posts"))
+            .bearer_auth(&self.cfg.token)
+            .json(&self.new_post(post, file_ids))
+            .send_recorded(self)?
+            .error_for_status()?
+            .json()?;
+        self.remember(post, &created);
+        Ok(created.into())
+    }
+
+    fn typing(&self, channel_id: &str) -> Result<()> {
+        let data = serde_json::json!({"channel_id": channel_id, "parent_id": ""});
+        self.ws_action("user_typing", data).map(|_| ())
+    }
+
+    fn delete(&self, post_id: &str) -> Result<()> {
+        self.client
+            .delete(self.url(&format!("/posts/{}", post_id)))
+            .bearer_auth(&self.cfg.token)
+            .send_recorded(self)?
+            .error_for_status()?;
+        self.answers.lock().unwrap().forget(post_id);
+        Ok(())
+    }
+}
+
+impl Recall for Mattermost {
+    fn answers(&self, post_id: &str) -> Vec<String> {
+        self.answers.lock().unwrap().answers(post_id)
+    }
+
+    fn recent(&self, channel_id: &str, n: usize) -> Vec<String> {
+        self.answers.lock().unwrap().recent(channel_id, n)
+    }
+}
+
+impl Mattermost {
+    /// Posts created by the bot, to carry over to the next process on upgrade.
+    pub fn save_answers(&self) -> serde_json::Value {
+        self.answers.lock().unwrap().save()
+    }
+
+    /// Take over the posts created by the previous process.
+    pub fn restore_answers(&self, saved: serde_json::Value) -> serde_json::Result<()> {
+        *self.answers.lock().unwrap() = Answers::load(saved)?;
+        Ok(())
+    }
+
+    /// Remember created, sent as post, to clean it up later.
+    fn remember(&self, post: &gm::Post, created: &Post) {
+        self.answers.lock().unwrap().record(
+            &created.channel_id,
+            &created.id,
+            post.answered(),
+        );
+    }
+
+    fn set_status(&self, status: &str) -> Result<()> {
+        let status = UserStatus {
+            user_id: &self.me.id,
+            status,
         };
         self.client
-            .post(&self.url("/posts"))
+            .put(self.url("/users/me/status"))
             .bearer_auth(&self.cfg.token)
-            .json(&mmpost)
-            .send()?;
+            .json(&status)
+            .send_recorded(self)?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+impl Presence for Mattermost {
+    fn online(&self) -> Result<()> {
+        self.set_status("online")
+    }
+
+    fn away(&self) -> Result<()> {
+        self.set_status("away")
+    }
+
+    fn custom_status(&self, emoji: &str, text: &str) -> Result<()> {
+        self.client
+            .put(self.url("/users/me/status/custom"))
+            .bearer_auth(&self.cfg.token)
+            .json(&CustomStatus { emoji, text })
+            .send_recorded(self)?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn clear_custom_status(&self) -> Result<()> {
+        self.client
+            .delete(self.url("/users/me/status/custom"))
+            .bearer_auth(&self.cfg.token)
+            .send_recorded(self)?
+            .error_for_status()?;
         Ok(())
     }
 }
@@ -152,7 +412,28 @@ impl Editor for Mattermost {
             .put(&self.url(&format!("/posts/{}/patch", post.id)))
             .bearer_auth(&self.cfg.token)
             .json(&edit)
-            .send()?;
+            .send_recorded(self)?;
+        Ok(())
+    }
+
+    fn edit_with_files(
+        &self,
+        post: &gm::Post,
+        message: &str,
+        files: &[gm::File],
+    ) -> Result<()> {
+        let file_ids = self.upload(&post.channel_id, files)?;
+        let edit = PostEdit {
+            message: Some(message),
+            file_ids: Some(file_ids.iter().map(|id| id.as_str()).collect()),
+        };
+
+        self.client
+            .put(self.url(&format!("/posts/{}/patch", post.id)))
+            .bearer_auth(&self.cfg.token)
+            .json(&edit)
+            .send_recorded(self)?
+            .error_for_status()?;
         Ok(())
     }
 }
@@ -191,20 +472,165 @@ impl Getter for Mattermost {
     }
 
     fn users_by_ids(&self, ids: Vec<&str>) -> Result<Vec<gm::User>> {
-        let r = self
+        let mut fusers: Vec<gm::User> = vec![];
+        let mut missing: Vec<&str> = vec![];
+        for id in ids.iter() {
+            match self.cache.users.get(&id.to_string()) {
+                Some(user) => fusers.push(user),
+                None => missing.push(id),
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(fusers);
+        }
+
+        let users: Vec<User> = self
             .client
             .post(self.url("/users/ids"))
             .bearer_auth(&self.cfg.token)
-            .json(&ids)
-            .send()?;
-
-        let users: Vec<User> = r.json()?;
+            .json(&missing)
+            .send_recorded(self)?
+            .error_for_status()?
+            .json()?;
 
-        let mut fusers: Vec<gm::User> = vec![];
-        for u in users.iter() {
-            fusers.push((*u).clone().into());
+        for u in users.into_iter() {
+            let user: gm::User = u.into();
+            self.cache
+                .usernames
+                .insert(user.username.clone(), user.id.clone());
+            self.cache.users.insert(user.id.clone(), user.clone());
+            fusers.push(user);
         }
 
         Ok(fusers)
     }
+
+    fn user_by_username(&self, username: &str) -> Result<Option<gm::User>> {
+        if let Some(id) = self.cache.usernames.get(&username.to_string()) {
+            if let Some(user) = self.cache.users.get(&id) {
+                return Ok(Some(user));
+            }
+        }
+
+        let user: Option<User> =
+            self.get_opt(&format!("/users/username/{}", username))?;
+        Ok(user.map(|u| {
+            let user: gm::User = u.into();
+            self.cache
+                .usernames
+                .insert(user.username.clone(), user.id.clone());
+            self.cache.users.insert(user.id.clone(), user.clone());
+            user
+        }))
+    }
+
+    fn channel_by_id(&self, channel_id: &str) -> Result<Option<gm::Channel>> {
+        if let Some(channel) = self.cache.channels.get(&channel_id.to_string()) {
+            return Ok(Some(channel));
+        }
+        let channel = self.get_opt(&format!("/channels/{}", channel_id))?;
+        Ok(self.cache_channel(channel))
+    }
+
+    fn channel_by_name(
+        &self,
+        team_id: &str,
+        name: &str,
+    ) -> Result<Option<gm::Channel>> {
+        let key = (team_id.to_string(), name.to_string());
+        if let Some(id) = self.cache.channel_names.get(&key) {
+            if let Some(channel) = self.cache.channels.get(&id) {
+                return Ok(Some(channel));
+            }
+        }
+        let channel =
+            self.get_opt(&format!("/teams/{}/channels/name/{}", team_id, name))?;
+        Ok(self.cache_channel(channel))
+    }
+
+    fn team_by_id(&self, team_id: &str) -> Result<Option<gm::Team>> {
+        if let Some(team) = self.cache.teams.get(&team_id.to_string()) {
+            return Ok(Some(team));
+        }
+        let team = self.get_opt(&format!("/teams/{}", team_id))?;
+        Ok(self.cache_team(team))
+    }
+
+    fn team_by_name(&self, name: &str) -> Result<Option<gm::Team>> {
+        if let Some(id) = self.cache.team_names.get(&name.to_string()) {
+            if let Some(team) = self.cache.teams.get(&id) {
+                return Ok(Some(team));
+            }
+        }
+        let team = self.get_opt(&format!("/teams/name/{}", name))?;
+        Ok(self.cache_team(team))
+    }
+
+    fn thread(&self, root_id: &str) -> Result<Vec<gm::Post>> {
+        let list: PostList = self
+            .client
+            .get(self.url(&format!("/posts/{}/thread", root_id)))
+            .bearer_auth(&self.cfg.token)
+            .send_recorded(self)?
+            .error_for_status()?
+            .json()?;
+
+        let mut posts: Vec<Post> = list
+            .posts
+            .into_values()
+            .filter(|p| p.delete_at == 0)
+            .collect();
+        posts.sort_by_key(|p| p.create_at);
+
+        Ok(posts.into_iter().map(|p| p.into()).collect())
+    }
+
+    fn channel_members(&self, channel_id: &str) -> Result<Vec<gm::User>> {
+        let ids = match self.cache.members.get(&channel_id.to_string()) {
+            Some(ids) => ids,
+            None => {
+                let per_page = 200;
+                let mut ids: Vec<String> = vec![];
+                for page in 0.. {
+                    let members: Vec<ChannelMember> = self
+                        .client
+                        .get(self.url(&format!("/channels/{}/members", channel_id)))
+                        .query(&[("page", page), ("per_page", per_page)])
+                        .bearer_auth(&self.cfg.token)
+                        .send_recorded(self)?
+                        .error_for_status()?
+                        .json()?;
+                    let count = members.len();
+                    ids.extend(members.into_iter().map(|m| m.user_id));
+                    if count < per_page {
+                        break;
+                    }
+                }
+                self.cache
+                    .members
+                    .insert(channel_id.to_string(), ids.clone());
+                ids
+            }
+        };
+
+        self.users_by_ids(ids.iter().map(|id| id.as_str()).collect())
+    }
+
+    fn emoji_exists(&self, name: &str) -> Result<bool> {
+        Ok(super::emoji::is_system(name)
+            || self.custom_emoji()?.iter().any(|n| n == name))
+    }
+
+    fn emoji_names(&self) -> Result<Vec<String>> {
+        let mut names = super::emoji::system_names();
+        names.extend(self.custom_emoji()?);
+        Ok(names)
+    }
+
+    fn permalink(&self, post_id: &str) -> Option<String> {
+        let site = self.cfg.api_url.trim_end_matches('/');
+        let site = site.strip_suffix("/api/v4").unwrap_or(site);
+        Some(format!("{}/_redirect/pl/{}", site, post_id))
+    }
 }
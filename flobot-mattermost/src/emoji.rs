@@ -0,0 +1,25 @@
+/// System emoji names, available on every server.
+const SYSTEM: &str = include_str!("emoji.txt");
+
+fn system() -> impl Iterator<Item = &'static str> {
+    SYSTEM
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Whether name is a system emoji.
+///
+/// ```rust
+/// # use flobot_mattermost::emoji::is_system;
+/// assert!(is_system("ok_hand"));
+/// assert!(is_system("+1"));
+/// assert!(!is_system("ok_hands"));
+/// ```
+pub fn is_system(name: &str) -> bool {
+    system().any(|n| n == name)
+}
+
+/// Every system emoji name.
+pub fn system_names() -> Vec<String> {
+    system().map(String::from).collect()
+}
@@ -1,3 +1,8 @@
+pub mod actions;
+pub mod cache;
 pub mod client;
+pub mod emoji;
 pub mod models;
+pub mod record;
+pub mod resume;
 pub mod websocket;
@@ -1,12 +1,70 @@
 use flobot_lib::models as gm;
+use flobot_lib::warn;
 use serde::{Deserialize, Serialize};
-use std::convert::Into;
+use std::collections::HashMap;
+use std::convert::{Into, TryFrom, TryInto};
 
 #[derive(Serialize)]
 pub struct Metadata {}
 
+#[derive(Serialize, Default)]
+pub struct Props {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Serialize)]
+pub struct Attachment {
+    pub fallback: String,
+    pub color: String,
+    pub pretext: String,
+    pub title: String,
+    pub text: String,
+    pub fields: Vec<Field>,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Serialize)]
+pub struct Field {
+    pub title: String,
+    pub value: String,
+    pub short: bool,
+}
+
 #[derive(Serialize)]
-pub struct Props {}
+pub struct Action {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<ActionOption>,
+    pub integration: Integration,
+}
+
+#[derive(Serialize)]
+pub struct ActionOption {
+    pub text: String,
+    pub value: String,
+}
+
+#[derive(Serialize)]
+pub struct Integration {
+    pub url: String,
+    pub context: HashMap<String, String>,
+}
+
+/// Sent by the server to the integration url when an action is used.
+#[derive(Deserialize)]
+pub struct ActionRequest {
+    pub user_id: String,
+    pub channel_id: String,
+    #[serde(default)]
+    pub team_id: String,
+    pub post_id: String,
+    #[serde(default)]
+    pub context: HashMap<String, serde_json::Value>,
+}
 
 #[derive(Serialize)]
 pub struct NewPost<'a> {
@@ -22,7 +80,7 @@ pub struct NewPost<'a> {
     pub parent_id: Option<String>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Post {
     pub id: String,
     pub message: String,
@@ -34,9 +92,46 @@ pub struct Post {
     pub user_id: String,
     pub channel_id: String,
     pub root_id: String,
+    #[serde(default)]
+    pub parent_id: String,
     pub original_id: String,
 }
 
+impl Post {
+    /// Post answered to, defaulting to the thread root as the server only keeps
+    /// track of threads.
+    fn parent(&self) -> String {
+        if self.parent_id.is_empty() {
+            self.root_id.clone()
+        } else {
+            self.parent_id.clone()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PostList {
+    pub order: Vec<String>,
+    pub posts: HashMap<String, Post>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Team {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Channel {
+    pub id: String,
+    pub team_id: String,
+    pub name: String,
+    pub display_name: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct CreateChannel<'a> {
     pub team_id: &'a str,
@@ -46,7 +141,7 @@ pub struct CreateChannel<'a> {
     pub type_: &'a str,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Reaction {
     pub user_id: String,
     pub post_id: String,
@@ -61,6 +156,34 @@ pub struct GenericID {
     pub request_id: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct FileInfo {
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+pub struct FileUploads {
+    pub file_infos: Vec<FileInfo>,
+}
+
+#[derive(Serialize)]
+pub struct EphemeralPost<'a> {
+    pub user_id: &'a str,
+    pub post: NewPost<'a>,
+}
+
+#[derive(Serialize)]
+pub struct UserStatus<'a> {
+    pub user_id: &'a str,
+    pub status: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct CustomStatus<'a> {
+    pub emoji: &'a str,
+    pub text: &'a str,
+}
+
 #[derive(Serialize)]
 pub struct UserID {
     pub user_id: String,
@@ -86,6 +209,59 @@ pub struct Hello {
 pub struct User {
     pub id: String,
     pub username: String,
+    #[serde(default)]
+    pub nickname: String,
+    #[serde(default)]
+    pub first_name: String,
+    #[serde(default)]
+    pub last_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChannelMember {
+    pub user_id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UserUpdated {
+    pub user: UserRef,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UserRef {
+    pub id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ChannelUpdated {
+    /// json encoded channel.
+    pub channel: String,
+}
+
+/// Data of user_added and user_removed events, the channel being broadcasted.
+#[derive(Deserialize, Serialize)]
+pub struct EmojiAdded {
+    pub emoji: String,
+}
+
+#[derive(Deserialize)]
+pub struct Emoji {
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Membership {
+    pub user_id: String,
+}
+
+/// Cached data made stale by an event.
+#[derive(Debug, PartialEq)]
+pub enum Stale {
+    User(String),
+    Channel(String),
+    Members(String),
+    /// custom emoji list.
+    Emoji,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -103,10 +279,16 @@ pub struct PostEdited {
     pub post: String,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct ReactionAdded {
+    pub reaction: String,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Status {
     pub status: String,
     pub error: Option<StatusDetails>,
+    pub seq_reply: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -119,44 +301,123 @@ pub struct StatusDetails {
     pub is_oauth: Option<bool>,
 }
 
-impl Into<gm::PostEdited> for PostEdited {
-    fn into(self) -> gm::PostEdited {
-        // FIXME: must still decode self.post
-        let post: Post = serde_json::from_str(&self.post).unwrap();
-        gm::PostEdited {
+impl TryFrom<PostEdited> for gm::PostEdited {
+    type Error = serde_json::Error;
+
+    fn try_from(edited: PostEdited) -> Result<Self, Self::Error> {
+        let post: Post = serde_json::from_str(&edited.post)?;
+        Ok(gm::PostEdited {
             user_id: post.user_id.clone(),
             message: post.message.clone(),
             id: post.id.clone(),
             channel_id: post.channel_id.clone(),
-            parent_id: post.root_id.clone(),
+            parent_id: post.parent(),
             root_id: post.root_id.clone(),
+        })
+    }
+}
+
+impl TryFrom<PostEdited> for gm::PostDeleted {
+    type Error = serde_json::Error;
+
+    fn try_from(deleted: PostEdited) -> Result<Self, Self::Error> {
+        // post_deleted carries the deleted post the same way post_edited does.
+        let post: Post = serde_json::from_str(&deleted.post)?;
+        Ok(gm::PostDeleted {
+            user_id: post.user_id,
+            id: post.id,
+            channel_id: post.channel_id,
+            root_id: post.root_id,
+        })
+    }
+}
+
+impl From<Post> for gm::Post {
+    fn from(post: Post) -> gm::Post {
+        gm::Post {
+            parent_id: post.parent(),
+            user_id: post.user_id,
+            root_id: post.root_id,
+            message: post.message,
+            channel_id: post.channel_id,
+            id: post.id,
+            team_id: "".to_string(),
+            channel_kind: gm::ChannelKind::Unknown,
+            sender_name: "".to_string(),
+            attachments: vec![],
         }
     }
 }
 
 impl Into<gm::User> for User {
     fn into(self) -> gm::User {
+        let full_name = format!("{} {}", self.first_name, self.last_name);
+        let display_name = if !self.nickname.is_empty() {
+            self.nickname.clone()
+        } else if !full_name.trim().is_empty() {
+            full_name.trim().to_string()
+        } else {
+            self.username.clone()
+        };
+
         gm::User {
             id: self.id,
-            display_name: self.username.clone(),
-            username: self.username.clone(),
+            display_name,
+            username: self.username,
         }
     }
 }
 
-impl Into<gm::Post> for Posted {
-    fn into(self) -> gm::Post {
-        // FIXME: must still decode self.post
-        let post: Post = serde_json::from_str(&self.post).unwrap();
-        gm::Post {
+/// Kind of a channel from its server type.
+pub fn channel_kind(type_: &str) -> gm::ChannelKind {
+    match type_ {
+        "O" => gm::ChannelKind::Public,
+        "P" => gm::ChannelKind::Private,
+        "D" => gm::ChannelKind::Direct,
+        "G" => gm::ChannelKind::Group,
+        _ => gm::ChannelKind::Unknown,
+    }
+}
+
+impl From<Channel> for gm::Channel {
+    fn from(channel: Channel) -> Self {
+        Self {
+            kind: channel_kind(&channel.type_),
+            id: channel.id,
+            team_id: channel.team_id,
+            name: channel.name,
+            display_name: channel.display_name,
+        }
+    }
+}
+
+impl From<Team> for gm::Team {
+    fn from(team: Team) -> Self {
+        Self {
+            id: team.id,
+            name: team.name,
+            display_name: team.display_name,
+        }
+    }
+}
+
+impl TryFrom<Posted> for gm::Post {
+    type Error = serde_json::Error;
+
+    fn try_from(posted: Posted) -> Result<Self, Self::Error> {
+        let post: Post = serde_json::from_str(&posted.post)?;
+        Ok(gm::Post {
             user_id: post.user_id.clone(),
             root_id: post.root_id.clone(),
-            parent_id: post.root_id.clone(),
+            parent_id: post.parent(),
             message: post.message.clone(),
             channel_id: post.channel_id.clone(),
             id: post.id.clone(),
-            team_id: self.team_id.clone(),
-        }
+            team_id: posted.team_id.clone(),
+            channel_kind: channel_kind(&posted.channel_type),
+            sender_name: posted.sender_name.trim_start_matches('@').to_string(),
+            attachments: vec![],
+        })
     }
 }
 
@@ -194,7 +455,7 @@ impl Into<gm::Status> for Status {
     }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Default)]
 pub struct Me {
     pub id: String,
     pub username: String,
@@ -210,7 +471,13 @@ pub struct Me {
 pub enum EventData {
     Posted(Posted),
     PostEdited(PostEdited),
+    ReactionAdded(ReactionAdded),
     Hello(Hello),
+    UserUpdated(UserUpdated),
+    ChannelUpdated(ChannelUpdated),
+    EmojiAdded(EmojiAdded),
+    // matches any data with a user_id: keep last.
+    Membership(Membership),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -237,14 +504,113 @@ pub enum MetaEvent {
     Unsupported(String),
 }
 
+impl MetaEvent {
+    /// Decode a websocket frame, unsupported if it can't be.
+    pub fn parse(txt: &str) -> Self {
+        serde_json::from_str(txt)
+            .unwrap_or_else(|_| MetaEvent::Unsupported(txt.to_string()))
+    }
+
+    /// Id of the connected user, sent by the server in hello events.
+    pub fn hello_user_id(&self) -> Option<&str> {
+        match self {
+            MetaEvent::Event(Event {
+                data: EventData::Hello(_),
+                broadcast,
+                ..
+            }) => Some(&broadcast.user_id),
+            _ => None,
+        }
+    }
+
+    /// The post carried by a posted event.
+    pub fn posted(&self) -> Option<Post> {
+        match self {
+            MetaEvent::Event(Event {
+                data: EventData::Posted(posted),
+                ..
+            }) => serde_json::from_str(&posted.post).ok(),
+            _ => None,
+        }
+    }
+
+    /// Cached data this event makes stale.
+    pub fn stale(&self) -> Option<Stale> {
+        let event = match self {
+            MetaEvent::Event(event) => event,
+            _ => return None,
+        };
+
+        match (&event.data, event.type_.as_str()) {
+            (EventData::UserUpdated(updated), "user_updated") => {
+                Some(Stale::User(updated.user.id.clone()))
+            }
+            (EventData::ChannelUpdated(updated), "channel_updated") => {
+                serde_json::from_str::<Channel>(&updated.channel)
+                    .ok()
+                    .map(|c| Stale::Channel(c.id))
+            }
+            (EventData::Membership(_), "user_added" | "user_removed") => {
+                Some(Stale::Members(event.broadcast.channel_id.clone()))
+            }
+            (EventData::EmojiAdded(_), "emoji_added") => Some(Stale::Emoji),
+            _ => None,
+        }
+    }
+
+    /// Whether this is the successful reply to the message sent with seq.
+    pub fn is_ok_reply(&self, seq: u64) -> bool {
+        match self {
+            MetaEvent::Status(status) => {
+                status.status == "OK" && status.seq_reply == Some(seq)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The event, or an unsupported one when the post it carries cannot be decoded.
+fn decoded(type_: String, event: Result<gm::Event, serde_json::Error>) -> gm::Event {
+    event.unwrap_or_else(|e| {
+        warn!("cannot decode {} event: {}", type_, e);
+        gm::Event::Unsupported(type_)
+    })
+}
+
 impl Into<gm::Event> for Event {
     fn into(self) -> gm::Event {
         match self.data {
-            EventData::Posted(posted) => gm::Event::Post(posted.into()),
+            EventData::Posted(posted) => {
+                decoded(self.type_, posted.try_into().map(gm::Event::Post))
+            }
             EventData::Hello(hello) => gm::Event::Hello(gm::Hello {
                 server_string: hello.server_version.clone(),
             }),
-            EventData::PostEdited(edited) => gm::Event::PostEdited(edited.into()),
+            EventData::PostEdited(deleted) if self.type_ == "post_deleted" => {
+                decoded(self.type_, deleted.try_into().map(gm::Event::PostDeleted))
+            }
+            EventData::PostEdited(edited) => {
+                decoded(self.type_, edited.try_into().map(gm::Event::PostEdited))
+            }
+            // reaction_removed has the same payload.
+            EventData::ReactionAdded(_) if self.type_ != "reaction_added" => {
+                gm::Event::Unsupported(self.type_)
+            }
+            EventData::ReactionAdded(added) => {
+                match serde_json::from_str::<Reaction>(&added.reaction) {
+                    Ok(reaction) => gm::Event::Reaction(gm::Reaction {
+                        channel_id: self.broadcast.channel_id,
+                        user_id: reaction.user_id,
+                        post_id: reaction.post_id,
+                        emoji_name: reaction.emoji_name,
+                    }),
+                    Err(e) => gm::Event::Unsupported(e.to_string()),
+                }
+            }
+            EventData::UserUpdated(_)
+            | EventData::ChannelUpdated(_)
+            | EventData::EmojiAdded(_)
+            | EventData::Membership(_) => gm::Event::Unsupported(self.type_),
         }
     }
 }
@@ -273,6 +639,7 @@ mod tests {
     fn post_valid() {
         let data = r#"{"event": "posted", "data": {"channel_display_name":"Town Square","channel_name":"town-square","channel_type":"O","post":"{\"id\":\"ghkm74cqzbnjxr5dx638k73xqa\",\"create_at\":1576937676623,\"update_at\":1576937676623,\"edit_at\":0,\"delete_at\":0,\"is_pinned\":false,\"user_id\":\"kh9859j8kir15dmxonsm8sxq1w\",\"channel_id\":\"amtak96j3br5iyokgunmf188jc\",\"root_id\":\"\",\"parent_id\":\"\",\"original_id\":\"\",\"message\":\"test\",\"type\":\"\",\"props\":{},\"hashtags\":\"\",\"pending_post_id\":\"kh9859j8kir15dmxonsm8sxq1w:1576937676569\",\"metadata\":{}}","sender_name":"@admin","team_id":"49ck75z1figmpjy6eknrohsjnw"}, "broadcast": {"omit_users":null,"user_id":"","channel_id":"amtak96j3br5iyokgunmf188jc","team_id":""}, "seq": 7}"#;
         let valid: MetaEvent = serde_json::from_str(data).unwrap();
+        assert_eq!("ghkm74cqzbnjxr5dx638k73xqa", valid.posted().unwrap().id);
         let event = match valid {
             MetaEvent::Event(event) => event,
             _ => panic!("wrong type"),
@@ -286,6 +653,10 @@ mod tests {
                 assert_eq!(event.channel_name, "town-square");
                 assert_eq!(event.channel_type, "O");
                 assert_ne!(event.post, "");
+
+                let post: gm::Post = event.try_into().unwrap();
+                assert_eq!(gm::ChannelKind::Public, post.channel_kind);
+                assert_eq!("admin", post.sender_name);
             }
             _ => panic!("event type not tested"),
         }
@@ -308,6 +679,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn post_deleted() {
+        let data = r#"{"event": "post_deleted", "data": {"delete_by": "nn751zdmhfgq9k8orsiyreonbc", "post": "{\"id\":\"f4nj6eim7ir8fm6w9a1r75zwmy\",\"create_at\":1586031101535,\"update_at\":1586031103044,\"edit_at\":0,\"delete_at\":0,\"is_pinned\":false,\"user_id\":\"nn751zdmhfgq9k8orsiyreonbc\",\"channel_id\":\"sxoe6m6y8fr13jcajmaqbqawfh\",\"root_id\":\"\",\"parent_id\":\"\",\"original_id\":\"\",\"message\":\"!joke\",\"type\":\"\",\"props\":{},\"hashtags\":\"\",\"pending_post_id\":\"\",\"metadata\":{}}"}, "broadcast": {"omit_users": null, "user_id": "", "channel_id": "sxoe6m6y8fr13jcajmaqbqawfh", "team_id": ""}, "seq": 7}"#;
+        let valid: MetaEvent = serde_json::from_str(data).unwrap();
+
+        match valid.into() {
+            gm::Event::PostDeleted(deleted) => {
+                assert_eq!("f4nj6eim7ir8fm6w9a1r75zwmy", deleted.id);
+                assert_eq!("sxoe6m6y8fr13jcajmaqbqawfh", deleted.channel_id);
+            }
+            _ => panic!("not a deleted post"),
+        }
+    }
+
+    #[test]
+    fn reaction_added() {
+        let data = r#"{"event": "reaction_added", "data": {"reaction": "{\"user_id\":\"nn751zdmhfgq9k8orsiyreonbc\",\"post_id\":\"f4nj6eim7ir8fm6w9a1r75zwmy\",\"emoji_name\":\"ok_hand\",\"create_at\":1586031103044}"}, "broadcast": {"omit_users": null, "user_id": "", "channel_id": "sxoe6m6y8fr13jcajmaqbqawfh", "team_id": ""}, "seq": 8}"#;
+        let valid: MetaEvent = serde_json::from_str(data).unwrap();
+
+        match valid.into() {
+            gm::Event::Reaction(reaction) => {
+                assert_eq!(reaction.channel_id, "sxoe6m6y8fr13jcajmaqbqawfh");
+                assert_eq!(reaction.user_id, "nn751zdmhfgq9k8orsiyreonbc");
+                assert_eq!(reaction.post_id, "f4nj6eim7ir8fm6w9a1r75zwmy");
+                assert_eq!(reaction.emoji_name, "ok_hand");
+            }
+            _ => panic!("wrong type"),
+        }
+    }
+
+    #[test]
+    fn reaction_removed_is_not_a_reaction() {
+        let data = r#"{"event": "reaction_removed", "data": {"reaction": "{\"user_id\":\"nn751zdmhfgq9k8orsiyreonbc\",\"post_id\":\"f4nj6eim7ir8fm6w9a1r75zwmy\",\"emoji_name\":\"ok_hand\",\"create_at\":1586031103044}"}, "broadcast": {"omit_users": null, "user_id": "", "channel_id": "sxoe6m6y8fr13jcajmaqbqawfh", "team_id": ""}, "seq": 9}"#;
+        let valid: MetaEvent = serde_json::from_str(data).unwrap();
+
+        match valid.into() {
+            gm::Event::Unsupported(type_) => assert_eq!("reaction_removed", type_),
+            _ => panic!("wrong type"),
+        }
+    }
+
+    #[test]
+    fn post_undecodable_is_unsupported() {
+        let data = r#"{"event": "post_edited", "data": {"post": "{\"id\":"}, "broadcast": {"omit_users": null, "user_id": "", "channel_id": "sxoe6m6y8fr13jcajmaqbqawfh", "team_id": ""}, "seq": 10}"#;
+        let valid: MetaEvent = serde_json::from_str(data).unwrap();
+
+        match valid.into() {
+            gm::Event::Unsupported(type_) => assert_eq!("post_edited", type_),
+            _ => panic!("wrong type"),
+        }
+    }
+
     #[test]
     #[should_panic]
     fn post_invalid() {
@@ -326,4 +749,49 @@ mod tests {
 
         assert_eq!("FAIL", status.status);
     }
+
+    #[test]
+    fn auth_ok() {
+        let data = r#"{"status": "OK", "seq_reply": 1}"#;
+        let valid: MetaEvent = serde_json::from_str(data).unwrap();
+
+        assert!(valid.is_ok_reply(1));
+        assert!(!valid.is_ok_reply(2));
+        assert!(valid.posted().is_none());
+    }
+
+    #[test]
+    fn action_request() {
+        let data = r#"{"user_id":"uid","user_name":"user","channel_id":"cid","channel_name":"town","team_id":"tid","team_domain":"team","post_id":"pid","trigger_id":"trig","type":"","data_source":"","context":{"secret":"s3cr3t","selected_option":"!ww vote bob"}}"#;
+        let request: ActionRequest = serde_json::from_str(data).unwrap();
+
+        assert_eq!("uid", request.user_id);
+        assert_eq!("pid", request.post_id);
+        assert_eq!(
+            Some("!ww vote bob"),
+            request
+                .context
+                .get("selected_option")
+                .and_then(|v| v.as_str())
+        );
+    }
+
+    #[test]
+    fn stale() {
+        let data = r#"{"event": "user_updated", "data": {"user": {"id": "uid", "username": "bob"}}, "broadcast": {"channel_id": "", "omit_users": null, "team_id": "", "user_id": ""}, "seq": 3}"#;
+        let event: MetaEvent = serde_json::from_str(data).unwrap();
+        assert_eq!(Some(Stale::User("uid".to_string())), event.stale());
+
+        let data = r#"{"event": "channel_updated", "data": {"channel": "{\"id\":\"cid\",\"team_id\":\"tid\",\"name\":\"town\",\"display_name\":\"Town\",\"type\":\"O\"}"}, "broadcast": {"channel_id": "cid", "omit_users": null, "team_id": "", "user_id": ""}, "seq": 4}"#;
+        let event: MetaEvent = serde_json::from_str(data).unwrap();
+        assert_eq!(Some(Stale::Channel("cid".to_string())), event.stale());
+
+        let data = r#"{"event": "user_added", "data": {"team_id": "tid", "user_id": "uid"}, "broadcast": {"channel_id": "cid", "omit_users": null, "team_id": "", "user_id": ""}, "seq": 5}"#;
+        let event: MetaEvent = serde_json::from_str(data).unwrap();
+        assert_eq!(Some(Stale::Members("cid".to_string())), event.stale());
+
+        let data = r#"{"event": "emoji_added", "data": {"emoji": "{\"id\":\"eid\",\"name\":\"parrot\"}"}, "broadcast": {"channel_id": "", "omit_users": null, "team_id": "", "user_id": ""}, "seq": 6}"#;
+        let event: MetaEvent = serde_json::from_str(data).unwrap();
+        assert_eq!(Some(Stale::Emoji), event.stale());
+    }
 }
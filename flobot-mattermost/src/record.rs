@@ -0,0 +1,198 @@
+use super::client::Mattermost;
+use super::models::MetaEvent;
+use flobot_lib::conf::Conf;
+use flobot_lib::metrics::Metric;
+use flobot_lib::models as gm;
+use flobot_lib::{error, info, warn};
+use reqwest::blocking::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+
+/// One line of a recording.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    /// raw websocket frame received from the server.
+    Ws { at: i64, frame: String },
+    /// websocket action sent to the server.
+    WsOut { at: i64, frame: String },
+    /// REST call made to the server, with the response status if any.
+    Rest {
+        at: i64,
+        method: String,
+        url: String,
+        body: Option<String>,
+        status: Option<u16>,
+    },
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Recorder appends websocket frames and REST calls to a JSONL file, to reproduce
+/// what the bot saw with replay.
+#[derive(Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<File>>,
+}
+
+impl Recorder {
+    /// Read BOT_RECORD, the file to append the recording to. Nothing is recorded
+    /// without it.
+    pub(crate) fn from_conf(cfg: &Conf) -> Option<Self> {
+        let path = cfg.var("RECORD").ok()?;
+        match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => {
+                info!("recording traffic of {} to {}", cfg.name, path);
+                Some(Self {
+                    file: Arc::new(Mutex::new(file)),
+                })
+            }
+            Err(e) => {
+                error!("cannot record to {}: {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut line = serde_json::to_string(entry).unwrap();
+        line.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            error!("recording error: {:?}", e);
+        }
+    }
+
+    pub(crate) fn ws(&self, frame: &str) {
+        self.write(&Entry::Ws {
+            at: now_ms(),
+            frame: frame.to_string(),
+        });
+    }
+
+    pub(crate) fn ws_out(&self, frame: &str) {
+        self.write(&Entry::WsOut {
+            at: now_ms(),
+            frame: frame.to_string(),
+        });
+    }
+}
+
+const API_CALLS: Metric = Metric::counter(
+    "flobot_api_calls_total",
+    "Calls to the Mattermost API, by method and response status.",
+);
+
+/// Send requests built from the client of a Mattermost, counting them and recording
+/// them when it has a recorder.
+pub(crate) trait Recorded {
+    fn send_recorded(self, mm: &Mattermost) -> reqwest::Result<Response>;
+}
+
+impl Recorded for RequestBuilder {
+    fn send_recorded(self, mm: &Mattermost) -> reqwest::Result<Response> {
+        let request = self.build()?;
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+        // uploaded files are not text: only their size is kept.
+        let body = mm.recorder.as_ref().and_then(|_| {
+            request.body().and_then(|b| b.as_bytes()).map(|b| {
+                String::from_utf8(b.to_vec())
+                    .unwrap_or_else(|_| format!("<{} bytes>", b.len()))
+            })
+        });
+
+        let res = mm.client.execute(request);
+        let status = res.as_ref().ok().map(|r| r.status().as_u16());
+        let label = status.map_or_else(|| "error".to_string(), |s| s.to_string());
+        API_CALLS.inc(&[("method", &method), ("status", &label)]);
+        if let Some(recorder) = &mm.recorder {
+            recorder.write(&Entry::Rest {
+                at: now_ms(),
+                method,
+                url,
+                body,
+                status,
+            });
+        }
+        res
+    }
+}
+
+/// What a recording replays.
+pub struct Recording {
+    /// id of the bot user, if the recording starts with the connection.
+    pub me: Option<String>,
+    pub events: Vec<gm::Event>,
+}
+
+/// Read a recording, decoding received websocket frames to events as the live
+/// connection would. REST calls and sent frames are skipped.
+pub fn replay<R: BufRead>(recording: R) -> std::io::Result<Recording> {
+    let mut replayed = Recording {
+        me: None,
+        events: vec![],
+    };
+    for line in recording.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(Entry::Ws { frame, .. }) => {
+                let event = MetaEvent::parse(&frame);
+                if let Some(me) = event.hello_user_id() {
+                    replayed.me = Some(me.to_string());
+                }
+                replayed.events.push(event.into());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("replay: skipping invalid line: {:?}", e),
+        }
+    }
+    Ok(replayed)
+}
+
+/// Read the recording at path, see replay.
+pub fn replay_file(path: &str) -> std::io::Result<Recording> {
+    replay(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_fixture() {
+        let recording = include_str!("../fixtures/session.jsonl");
+        let recording = replay(recording.as_bytes()).unwrap();
+        assert_eq!(Some("9s8f7pqw1bd5uxkk3tc1nxr3ho"), recording.me.as_deref());
+        let events = recording.events;
+        assert_eq!(5, events.len());
+
+        match &events[0] {
+            gm::Event::Hello(hello) => assert_eq!("5.37.0", hello.server_string),
+            e => panic!("not hello: {:?}", e),
+        }
+        match &events[2] {
+            gm::Event::Post(post) => {
+                assert_eq!("!joke", post.message);
+                assert_eq!("admin", post.sender_name);
+            }
+            e => panic!("not a post: {:?}", e),
+        }
+        match &events[3] {
+            gm::Event::PostEdited(edited) => assert_eq!("!joke please", edited.message),
+            e => panic!("not an edit: {:?}", e),
+        }
+        match &events[4] {
+            gm::Event::PostDeleted(deleted) => {
+                assert_eq!("ghkm74cqzbnjxr5dx638k73xqa", deleted.id)
+            }
+            e => panic!("not a deletion: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use super::models::{channel_kind, Channel, PostList, Team};
+use super::record::Recorded;
+use flobot_lib::client::Result;
+use flobot_lib::models::{self as gm, Event};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::Sender as ChannelSender;
+
+/// How many post ids are remembered to deduplicate replayed posts.
+const MAX_SEEN: usize = 5000;
+
+/// Never catch up on posts older than this, in milliseconds: answering hours old
+/// commands would be more confusing than helpful.
+const MAX_LOOKBACK: u64 = 3600 * 1000;
+
+/// Local and server clocks may differ: catch up a bit earlier than needed, already
+/// seen posts are dropped anyway.
+const MARGIN: u64 = 30 * 1000;
+
+fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+/// Resume tracks posts received from the websocket, so posts missed while
+/// disconnected can be fetched back and replayed without duplicates.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Resume {
+    /// last post creation timestamp received, per channel.
+    last: HashMap<String, u64>,
+    /// ids of posts already sent to the instance, oldest first.
+    seen: VecDeque<String>,
+    #[serde(skip)]
+    seen_set: HashSet<String>,
+    /// catch up from here on channels without any known post. None until the
+    /// websocket is lost: a fresh start has nothing to catch up.
+    disconnected_at: Option<u64>,
+}
+
+impl Resume {
+    /// Record a post. Returns false if it was already seen and must be dropped.
+    ///
+    /// ```rust
+    /// # use flobot_mattermost::resume::Resume;
+    /// let mut resume = Resume::default();
+    /// assert!(resume.see("channel", "post", 1));
+    /// assert!(!resume.see("channel", "post", 1));
+    /// ```
+    pub fn see(&mut self, channel_id: &str, post_id: &str, create_at: u64) -> bool {
+        if self.seen_set.contains(post_id) {
+            return false;
+        }
+
+        self.seen_set.insert(post_id.to_string());
+        self.seen.push_back(post_id.to_string());
+        while self.seen.len() > MAX_SEEN {
+            if let Some(id) = self.seen.pop_front() {
+                self.seen_set.remove(&id);
+            }
+        }
+
+        let last = self.last.entry(channel_id.to_string()).or_insert(0);
+        *last = create_at.max(*last);
+
+        true
+    }
+
+    /// The websocket connection was lost.
+    pub fn disconnected(&mut self) {
+        self.disconnected_at = Some(now_ms());
+    }
+
+    /// Timestamp from which posts of channel_id must be fetched back, None if
+    /// nothing was missed.
+    fn since(&self, channel_id: &str) -> Option<u64> {
+        let disconnected_at = self.disconnected_at?;
+        let since = *self.last.get(channel_id).unwrap_or(&disconnected_at);
+        Some(
+            since
+                .saturating_sub(MARGIN)
+                .max(now_ms().saturating_sub(MAX_LOOKBACK)),
+        )
+    }
+}
+
+impl super::client::Mattermost {
+    fn my_channels(&self) -> Result<Vec<Channel>> {
+        let teams: Vec<Team> = self
+            .client
+            .get(self.url("/users/me/teams"))
+            .bearer_auth(&self.cfg.token)
+            .send_recorded(self)?
+            .error_for_status()?
+            .json()?;
+
+        let mut channels: Vec<Channel> = vec![];
+        for team in teams.iter() {
+            let mut team_channels: Vec<Channel> = self
+                .client
+                .get(self.url(&format!("/users/me/teams/{}/channels", team.id)))
+                .bearer_auth(&self.cfg.token)
+                .send_recorded(self)?
+                .error_for_status()?
+                .json()?;
+            channels.append(&mut team_channels);
+        }
+
+        // direct and group channels are listed in every team.
+        let mut ids = HashSet::new();
+        channels.retain(|c| ids.insert(c.id.clone()));
+
+        Ok(channels)
+    }
+
+    /// Posts seen, to carry over to the next process on upgrade: posts sent in
+    /// between are caught up once connected.
+    pub fn save_resume(&self) -> serde_json::Value {
+        serde_json::to_value(&*self.resume.lock().unwrap()).unwrap_or_default()
+    }
+
+    /// Take over the posts seen by the previous process.
+    pub fn restore_resume(&self, saved: serde_json::Value) -> serde_json::Result<()> {
+        let mut resume: Resume = serde_json::from_value(saved)?;
+        resume.seen_set = resume.seen.iter().cloned().collect();
+        *self.resume.lock().unwrap() = resume;
+        Ok(())
+    }
+
+    /// Fetch posts created since the websocket was lost or since the last post seen
+    /// on each channel the bot is member of, and send the ones not seen yet. Posts
+    /// before a fresh start are not caught up: they may have been handled already.
+    pub fn catch_up(&self, sender: &ChannelSender<Event>) -> Result<usize> {
+        let mut replayed = 0;
+        if self.resume.lock().unwrap().disconnected_at.is_none() {
+            return Ok(replayed);
+        }
+
+        for channel in self.my_channels()?.iter() {
+            let since = match self.resume.lock().unwrap().since(&channel.id) {
+                Some(since) => since,
+                None => continue,
+            };
+            let list: PostList = self
+                .client
+                .get(self.url(&format!("/channels/{}/posts", channel.id)))
+                .query(&[("since", since)])
+                .bearer_auth(&self.cfg.token)
+                .send_recorded(self)?
+                .error_for_status()?
+                .json()?;
+
+            // since also returns posts edited or deleted after that time.
+            let mut posts: Vec<_> = list
+                .posts
+                .into_values()
+                .filter(|p| p.create_at > since && p.delete_at == 0)
+                .collect();
+            posts.sort_by_key(|p| p.create_at);
+
+            for post in posts {
+                if !self.resume.lock().unwrap().see(
+                    &post.channel_id,
+                    &post.id,
+                    post.create_at,
+                ) {
+                    continue;
+                }
+
+                let mut post: gm::Post = post.into();
+                post.team_id = channel.team_id.clone();
+                post.channel_kind = channel_kind(&channel.type_);
+                if sender.send(Event::Post(post)).is_err() {
+                    return Ok(replayed);
+                }
+                replayed += 1;
+            }
+        }
+
+        Ok(replayed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_start_catches_nothing_up() {
+        let mut resume = Resume::default();
+        assert!(resume.see("channel", "post", now_ms()));
+        assert_eq!(None, resume.since("channel"));
+        assert_eq!(None, resume.since("other"));
+    }
+
+    #[test]
+    fn catch_up_after_disconnection() {
+        let mut resume = Resume::default();
+        let seen_at = now_ms() - 60 * 1000;
+        resume.see("channel", "post", seen_at);
+        resume.disconnected();
+        assert_eq!(Some(seen_at - MARGIN), resume.since("channel"));
+        let other = resume.since("other").unwrap();
+        assert!(other > seen_at && other <= now_ms() - MARGIN);
+    }
+
+    #[test]
+    fn restored_resume_catches_up() {
+        let saved = serde_json::json!({
+            "last": {"channel": now_ms() - 60 * 1000},
+            "seen": ["post"],
+            "disconnected_at": now_ms() - 10 * 1000,
+        });
+        let resume: Resume = serde_json::from_value(saved).unwrap();
+        assert!(resume.since("channel").is_some());
+        assert!(resume.since("other").is_some());
+    }
+}
@@ -1,87 +1,348 @@
+use super::client::Mattermost;
 use super::models::MetaEvent;
+use flobot_lib::client::{Error, Notifier};
+use flobot_lib::health::Heartbeat;
+use flobot_lib::metrics::Metric;
 use flobot_lib::models::Event;
-use serde_json::json;
+use flobot_lib::{error, info, warn};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender as ChannelSender;
-use ws::{connect, CloseCode, Handler, Handshake, Message, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use ws::util::Token;
+use ws::{connect, CloseCode, Frame, Handler, Handshake, Message, Sender};
 
 type Result = ws::Result<()>;
 
+/// Timer token of the periodic liveness check.
+const PING: Token = Token(1);
+
+/// How often the server is pinged, in milliseconds.
+const PING_EVERY: u64 = 30 * 1000;
+
+/// Without any frame from the server for that long, the connection is considered
+/// dead: a half-open TCP connection would otherwise never return.
+const DEAD_AFTER: Duration = Duration::from_secs(75);
+
+/// First reconnection delay, doubled on each failed attempt up to MAX_DELAY.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// How often the reconnection delay checks whether the client is closing.
+const CLOSING_TICK: Duration = Duration::from_millis(250);
+
+const RECONNECTS: Metric = Metric::counter(
+    "flobot_websocket_reconnects_total",
+    "Connections authenticated again after the websocket was lost.",
+);
+
+/// Delay before reconnection attempt number `attempt`, starting at 0.
+fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY
+        .checked_mul(1 << attempt.min(16))
+        .unwrap_or(MAX_DELAY)
+        .min(MAX_DELAY)
+}
+
+/// The open connection, shared with the client to send actions such as typing.
+#[derive(Default)]
+pub(crate) struct Link {
+    out: Option<Sender>,
+    seq: u64,
+}
+
 struct MattermostWS {
     out: Sender,
     send: ChannelSender<Event>,
-    token: String,
-    seq: u64,
+    client: Mattermost,
+    auth_seq: u64,
+    ping_seq: u64,
+    last_seen: Instant,
+    /// whether a previous connection was authenticated, so handlers must resync.
+    reconnect: bool,
+    /// set when the instance no longer receives events: stop listening.
+    stop: Arc<AtomicBool>,
+}
+
+fn internal(e: Error) -> ws::Error {
+    ws::Error::new(ws::ErrorKind::Internal, e.to_string())
+}
+
+impl MattermostWS {
+    /// Replay posts missed while disconnected or authenticating, without blocking
+    /// the websocket, then tell handlers the connection is back.
+    fn catch_up(&self) {
+        let client = self.client.clone();
+        let send = self.send.clone();
+        let reconnect = self.reconnect;
+        if reconnect {
+            RECONNECTS.inc(&[]);
+        }
+        std::thread::spawn(move || {
+            match client.catch_up(&send) {
+                Ok(0) => {}
+                Ok(replayed) => {
+                    info!("websocket catch up: {} posts replayed", replayed)
+                }
+                Err(e) => error!("websocket catch up error: {:?}", e),
+            };
+            if reconnect {
+                let _ = client.debug("websocket reconnected");
+                let _ = send.send(Event::Reconnected);
+            }
+        });
+    }
+
+    /// Whether nothing was received for so long the connection must be dropped.
+    fn is_dead(&self) -> bool {
+        self.last_seen.elapsed() > DEAD_AFTER
+    }
 }
 
 impl Handler for MattermostWS {
     fn on_open(&mut self, _: Handshake) -> Result {
-        self.seq += 1;
-        let auth = json!({
-            "action": "authentication_challenge",
-            "data": {"token": self.token.clone()},
-            "seq": self.seq,
-        });
-        let res = self.out.send(Message::Text(auth.to_string()));
+        if self.client.closing.load(Ordering::SeqCst) {
+            return self.out.close(CloseCode::Away);
+        }
+        *self.client.link.lock().unwrap() = Link {
+            out: Some(self.out.clone()),
+            seq: 0,
+        };
+        let token = json!({"token": self.client.cfg.token.clone()});
+        self.auth_seq = self
+            .client
+            .ws_action("authentication_challenge", token)
+            .map_err(internal)?;
+        info!("websocket connected!");
+
+        self.out.timeout(PING_EVERY, PING)
+    }
+
+    fn on_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        self.last_seen = Instant::now();
+        self.client.heartbeat.beat();
+        if frame.has_rsv1() || frame.has_rsv2() || frame.has_rsv3() {
+            return Err(ws::Error::new(
+                ws::ErrorKind::Protocol,
+                "Encountered frame with reserved bits set.",
+            ));
+        }
+        Ok(Some(frame))
+    }
 
-        if res.is_ok() {
-            println!("websocket connected!");
+    fn on_timeout(&mut self, event: Token) -> Result {
+        if event != PING {
+            return Ok(());
         }
 
-        res
+        if self.is_dead() {
+            warn!(
+                "websocket silent for {} seconds, dropping connection",
+                self.last_seen.elapsed().as_secs()
+            );
+            // a close handshake would never complete on a dead connection.
+            return self.out.shutdown();
+        }
+
+        self.ping_seq = self
+            .client
+            .ws_action("ping", Value::Null)
+            .map_err(internal)?;
+        self.out.timeout(PING_EVERY, PING)
     }
 
     fn on_message(&mut self, msg: Message) -> Result {
         let txt = msg.as_text().unwrap();
-        let event: MetaEvent = match serde_json::from_str(txt) {
-            Ok(v) => v,
-            Err(_e) => MetaEvent::Unsupported(msg.to_string()),
-        };
+        if let Some(recorder) = &self.client.recorder {
+            recorder.ws(txt);
+        }
+        let event = MetaEvent::parse(txt);
+
+        if event.is_ok_reply(self.ping_seq) {
+            return Ok(());
+        }
+
+        if let Some(stale) = event.stale() {
+            self.client.cache.invalidate(&stale);
+        }
+
+        if event.is_ok_reply(self.auth_seq) {
+            self.client.connected.store(true, Ordering::SeqCst);
+            self.catch_up();
+        }
+
+        if let Some(post) = event.posted() {
+            let mut resume = self.client.resume.lock().unwrap();
+            if !resume.see(&post.channel_id, &post.id, post.create_at) {
+                return Ok(());
+            }
+        }
 
         match self.send.send(event.into()) {
-            Err(e) => self.out.close_with_reason(CloseCode::Error, e.to_string()),
+            Err(e) => {
+                self.stop.store(true, Ordering::SeqCst);
+                self.out.close_with_reason(CloseCode::Error, e.to_string())
+            }
             Ok(()) => Ok(()),
         }
     }
 }
 
 impl super::client::Mattermost {
+    /// Beats on each frame received: it stops while the websocket is down.
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// Whether the websocket is currently connected and authenticated.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Connection and last frame received, for state dumps.
+    pub fn state(&self) -> String {
+        let connection = if self.closing.load(Ordering::SeqCst) {
+            "closing"
+        } else if self.is_connected() {
+            "connected"
+        } else {
+            "disconnected"
+        };
+        format!(
+            "{}, last frame {} seconds ago",
+            connection,
+            self.heartbeat.elapsed().as_secs()
+        )
+    }
+
+    /// Close the websocket and stop reconnecting: listen returns shortly after.
+    pub fn close(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+        if let Some(out) = &self.link.lock().unwrap().out {
+            if let Err(e) = out.close(CloseCode::Away) {
+                warn!("websocket close error: {:?}", e);
+            }
+        }
+    }
+
+    /// Sleep for delay, or until the client is closing.
+    fn wait_closing(&self, delay: Duration) {
+        let deadline = Instant::now() + delay;
+        while !self.closing.load(Ordering::SeqCst) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return;
+            }
+            std::thread::sleep(left.min(CLOSING_TICK));
+        }
+    }
+
+    /// Send action with data over the websocket, returning the seq of the request.
+    pub(crate) fn ws_action(
+        &self,
+        action: &str,
+        data: Value,
+    ) -> flobot_lib::client::Result<u64> {
+        let mut link = self.link.lock().unwrap();
+        link.seq += 1;
+        let mut request = json!({"action": action, "seq": link.seq});
+        if !data.is_null() {
+            request["data"] = data;
+        }
+        if let Some(recorder) = &self.recorder {
+            let mut recorded = request.clone();
+            if action == "authentication_challenge" {
+                recorded["data"] = json!({"token": "<redacted>"});
+            }
+            recorder.ws_out(&recorded.to_string());
+        }
+        match &link.out {
+            Some(out) => out
+                .send(Message::Text(request.to_string()))
+                .map(|_| link.seq)
+                .map_err(|e| Error::Other(e.to_string())),
+            None => Err(Error::Other("websocket not connected".to_string())),
+        }
+    }
+
+    /// Receive events from the websocket and send them to sender, reconnecting
+    /// whenever the connection is lost. Returns once sender is disconnected or the
+    /// client is closed.
     pub fn listen(&self, sender: ChannelSender<Event>) {
         let mut url = self.cfg.ws_url.clone();
         url.push_str("/api/v4/websocket");
 
-        let reco_time = std::time::Duration::from_secs(5);
-        let mut retry = true;
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut attempt = 0;
+        let mut ever_connected = false;
 
-        while retry {
-            if let Err(e) = connect(url.clone(), |out| MattermostWS {
+        loop {
+            let res = connect(url.clone(), |out| MattermostWS {
                 out,
                 send: sender.clone(),
-                token: self.cfg.token.clone(),
-                seq: 0,
-            }) {
-                match e.kind {
-                    ws::ErrorKind::Io(details) => {
-                        println!("websocket io error: {:?}", details);
-                    }
-                    e => {
-                        println!(
-                            "websocket disconnected with unrecoverable error: {:?}",
-                            e
-                        );
-                        retry = false;
-                    }
-                }
+                client: self.clone(),
+                auth_seq: 0,
+                ping_seq: 0,
+                last_seen: Instant::now(),
+                reconnect: ever_connected,
+                stop: stop.clone(),
+            });
+
+            self.link.lock().unwrap().out = None;
+            let was_connected = self.connected.swap(false, Ordering::SeqCst);
+            // failed attempts must not move the start of the outage.
+            if was_connected {
+                self.resume.lock().unwrap().disconnected();
+            }
+
+            if stop.load(Ordering::SeqCst) {
+                info!("websocket: instance is gone, stop listening");
+                return;
+            }
+            if self.closing.load(Ordering::SeqCst) {
+                info!("websocket: closed, stop listening");
+                return;
+            }
+
+            let reason = match res {
+                Ok(()) => "connection closed".to_string(),
+                Err(e) => format!("{:?}", e.kind),
+            };
+
+            if was_connected {
+                ever_connected = true;
+                attempt = 0;
+                let _ = self.debug(&format!("websocket lost: {}", reason));
+            } else if attempt > 0 {
+                warn!("websocket reconnection attempt {} failed", attempt);
             }
 
-            if retry {
-                println!(
-                    "websocket returned, retrying in {} seconds",
-                    reco_time.as_secs()
-                );
-                std::thread::sleep(reco_time);
-            } else {
+            let delay = backoff(attempt);
+            info!(
+                "websocket returned ({}), retrying in {} seconds",
+                reason,
+                delay.as_secs()
+            );
+            self.wait_closing(delay);
+            if self.closing.load(Ordering::SeqCst) {
+                info!("websocket: closed, stop listening");
                 return;
             }
+            attempt += 1;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_until_max() {
+        assert_eq!(Duration::from_secs(1), backoff(0));
+        assert_eq!(Duration::from_secs(8), backoff(3));
+        assert_eq!(MAX_DELAY, backoff(9));
+        assert_eq!(MAX_DELAY, backoff(u32::MAX));
+    }
+}
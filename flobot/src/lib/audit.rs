@@ -0,0 +1,198 @@
+use crate::db;
+use crate::db::models::NewAudit;
+use chrono::{Local, TimeZone};
+use flobot_lib::client;
+use flobot_lib::handler::{Handler as BotHandler, Result};
+use flobot_lib::models::Post;
+use flobot_lib::warn;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Modules recording their commands, to tell them from usernames in `!audit`.
+pub const MODULES: [&str; 5] = ["trigger", "edits", "joke", "sms", "werewolf"];
+
+/// Most entries `!audit` shows.
+const MAX_ENTRIES: i64 = 30;
+/// Longest command shown, in chars.
+const MAX_COMMAND: usize = 80;
+
+/// Record post, a state-changing command of module, and how it went. Failing to
+/// record does not fail the command.
+pub fn record<D, T, E>(
+    db: &D,
+    post: &Post,
+    module: &str,
+    outcome: &std::result::Result<T, E>,
+) where
+    D: db::Audit + ?Sized,
+    E: Debug,
+{
+    record_command(db, post, module, &post.message, outcome)
+}
+
+/// Like record, for commands post alone does not describe.
+pub fn record_command<D, T, E>(
+    db: &D,
+    post: &Post,
+    module: &str,
+    command: &str,
+    outcome: &std::result::Result<T, E>,
+) where
+    D: db::Audit + ?Sized,
+    E: Debug,
+{
+    let outcome = match outcome {
+        Ok(_) => "ok".to_string(),
+        Err(e) => format!("error: {:?}", e),
+    };
+    let entry = NewAudit {
+        at_unixts: Local::now().timestamp(),
+        team_id: &post.team_id,
+        channel_id: &post.channel_id,
+        user_id: &post.user_id,
+        module,
+        command,
+        outcome: &outcome,
+    };
+    if let Err(e) = db.record(&entry) {
+        warn!("cannot record {} command in audit: {:?}", module, e);
+    }
+}
+
+/// Lets admins see who ran state-changing commands.
+pub struct Handler<C, D> {
+    client: C,
+    db: Arc<D>,
+    /// usernames allowed to read the audit.
+    admins: Vec<String>,
+    match_audit: Regex,
+}
+
+impl<C, D> Handler<C, D>
+where
+    C: client::Sender + client::Getter,
+    D: db::Audit,
+{
+    pub fn new(client: C, db: Arc<D>, admins: Vec<String>) -> Self {
+        Self {
+            client,
+            db,
+            admins,
+            match_audit: Regex::new(
+                r"^!audit(?:[\s]+([^\s]+))?(?:[\s]+([^\s]+))?[\s]*$",
+            )
+            .unwrap(),
+        }
+    }
+
+    fn username(
+        &self,
+        usernames: &mut HashMap<String, String>,
+        user_id: &str,
+    ) -> String {
+        if let Some(username) = usernames.get(user_id) {
+            return username.clone();
+        }
+        let username = match self.client.users_by_ids(vec![user_id]) {
+            Ok(users) if !users.is_empty() => users[0].username.clone(),
+            _ => user_id.to_string(),
+        };
+        usernames.insert(user_id.to_string(), username.clone());
+        username
+    }
+
+    fn audit(
+        &self,
+        post: &Post,
+        module: Option<&str>,
+        username: Option<&str>,
+    ) -> Result {
+        let user_id = match username {
+            Some(username) => match self.client.user_by_username(username)? {
+                Some(user) => Some(user.id),
+                None => {
+                    return Ok(self
+                        .client
+                        .ephemeral(post, &format!("connais pas `{}`", username))?)
+                }
+            },
+            None => None,
+        };
+
+        let entries =
+            self.db
+                .entries(&post.team_id, module, user_id.as_deref(), MAX_ENTRIES)?;
+        if entries.is_empty() {
+            return Ok(self.client.ephemeral(post, "rien à signaler")?);
+        }
+
+        let mut usernames = HashMap::new();
+        let mut out = String::from("Dernières commandes :\n");
+        for entry in entries.iter() {
+            let at = Local
+                .timestamp_opt(entry.at_unixts, 0)
+                .single()
+                .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            let mut command: String = entry.command.chars().take(MAX_COMMAND).collect();
+            if command.len() < entry.command.len() {
+                command.push('…');
+            }
+            out.push_str(&format!(
+                " * {} @{} {} `{}` → {}\n",
+                at,
+                self.username(&mut usernames, &entry.user_id),
+                entry.module,
+                command.replace('`', "'"),
+                entry.outcome
+            ));
+        }
+        Ok(self.client.ephemeral(post, &out)?)
+    }
+}
+
+impl<C, D> BotHandler for Handler<C, D>
+where
+    C: client::Sender + client::Getter,
+    D: db::Audit,
+{
+    type Data = Post;
+
+    fn name(&self) -> String {
+        "audit".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(format!(
+            "```
+Who ran commands changing the bot, and how it went. Admins only.
+
+!audit [module] [user] # modules: {}
+```",
+            MODULES.join(", ")
+        ))
+    }
+
+    fn handle(&self, post: &Post) -> Result {
+        let captures = match self.match_audit.captures(&post.message) {
+            Some(captures) => captures,
+            None => return Ok(()),
+        };
+
+        if !client::is_admin(&self.client, &self.admins, post) {
+            return Ok(self.client.reply(post, "réservé aux admins du bot.")?);
+        }
+
+        let first = captures.get(1).map(|m| m.as_str());
+        let second = captures.get(2).map(|m| m.as_str().trim_start_matches('@'));
+        let (module, username) = match (first, second) {
+            (Some(first), None) if !MODULES.contains(&first) => {
+                (None, Some(first.trim_start_matches('@')))
+            }
+            (first, second) => (first, second),
+        };
+        self.audit(post, module, username)
+    }
+}
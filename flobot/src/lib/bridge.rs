@@ -0,0 +1,328 @@
+use crate::db;
+use flobot_lib::client;
+use flobot_lib::handler::{Handler, Result};
+use flobot_lib::models::{Event, Post, PostEdited, Reaction};
+use flobot_lib::tempo::Tempo;
+use regex::Regex;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// How many posts are remembered in order to propagate edits and reactions.
+const MAX_LINKS: usize = 2000;
+
+/// How long a mirrored message is remembered to ignore it if it comes back.
+const ECHO_DELAY: Duration = Duration::from_secs(600);
+
+/// A post on a given server.
+type Located = (String, Post);
+
+/// Links between posts and their copies, in both directions.
+/// Oldest links are forgotten first.
+#[derive(Default)]
+struct Links {
+    links: HashMap<(String, String), Vec<Located>>,
+    order: VecDeque<(String, String)>,
+}
+
+impl Links {
+    fn insert(&mut self, from: &Located, to: &Located) {
+        let key = (from.0.clone(), from.1.id.clone());
+        if !self.links.contains_key(&key) {
+            self.order.push_back(key.clone());
+        }
+        self.links.entry(key).or_default().push(to.clone());
+
+        while self.order.len() > MAX_LINKS {
+            if let Some(key) = self.order.pop_front() {
+                self.links.remove(&key);
+            }
+        }
+    }
+
+    fn link(&mut self, source: &Located, copy: &Located) {
+        self.insert(source, copy);
+        self.insert(copy, source);
+    }
+
+    fn get(&self, server: &str, post_id: &str) -> Vec<Located> {
+        self.links
+            .get(&(server.to_string(), post_id.to_string()))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// State shared by the bridge handlers of every connection: clients to reach
+/// each server and links between mirrored posts.
+pub struct Network<C> {
+    clients: RwLock<HashMap<String, C>>,
+    links: Mutex<Links>,
+    echoes: Tempo,
+}
+
+impl<C> Default for Network<C> {
+    fn default() -> Self {
+        Self {
+            clients: RwLock::new(HashMap::new()),
+            links: Mutex::new(Links::default()),
+            echoes: Tempo::new(),
+        }
+    }
+}
+
+impl<C: Clone> Network<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make server reachable through client.
+    pub fn join(&self, server: &str, client: C) {
+        self.clients
+            .write()
+            .unwrap()
+            .insert(server.to_string(), client);
+    }
+
+    fn client(&self, server: &str) -> Option<C> {
+        self.clients.read().unwrap().get(server).cloned()
+    }
+}
+
+/// Bridge mirrors messages between channel pairs stored in the database, on the
+/// same server or across servers managed by the bot.
+pub struct Bridge<C, D> {
+    server: String,
+    client: C,
+    db: Arc<D>,
+    network: Arc<Network<C>>,
+    admins: Vec<String>,
+    match_add: Regex,
+    match_del: Regex,
+    match_list: Regex,
+}
+
+impl<C, D> Bridge<C, D>
+where
+    C: client::Sender + client::Editor + client::Getter + Clone,
+    D: db::Bridge,
+{
+    pub fn new(
+        server: &str,
+        client: C,
+        db: Arc<D>,
+        network: Arc<Network<C>>,
+        admins: Vec<String>,
+    ) -> Self {
+        Self {
+            server: server.to_string(),
+            client,
+            db,
+            network,
+            admins,
+            match_add: Regex::new(r"^!bridge[\s]+add[\s]+([\S]+)[\s]+([\S]+)[\s]*$")
+                .unwrap(),
+            match_del: Regex::new(r"^!bridge[\s]+del[\s]+([0-9]+)[\s]*$").unwrap(),
+            match_list: Regex::new(r"^!bridge[\s]+list[\s]*$").unwrap(),
+        }
+    }
+
+    fn author(&self, user_id: &str) -> String {
+        match self.client.users_by_ids(vec![user_id]) {
+            Ok(users) if !users.is_empty() => users[0].username.clone(),
+            _ => user_id.to_string(),
+        }
+    }
+
+    fn format(&self, user_id: &str, message: &str) -> String {
+        format!(
+            "[{}] **{}**: {}",
+            self.server,
+            self.author(user_id),
+            message
+        )
+    }
+
+    fn handle_command(&self, post: &Post) -> Result {
+        let message = &post.message;
+
+        if self.match_list.is_match(message) {
+            let bridges = self.db.list(&self.server)?;
+            if bridges.is_empty() {
+                return Ok(self.client.reply(post, "Aucun pont.")?);
+            }
+
+            let mut msg = String::from("Ponts :\n");
+            for b in bridges {
+                msg.push_str(&format!(
+                    " * {}: `{}/{}` <-> `{}/{}`\n",
+                    b.id, b.from_server, b.from_channel, b.to_server, b.to_channel
+                ));
+            }
+            return Ok(self.client.reply(post, &msg)?);
+        }
+
+        if !client::is_admin(&self.client, &self.admins, post) {
+            return Ok(self.client.reply(post, "réservé aux admins du bot.")?);
+        }
+
+        if let Some(captures) = self.match_add.captures(message) {
+            let server = captures.get(1).unwrap().as_str();
+            let channel = captures.get(2).unwrap().as_str();
+
+            if self.network.client(server).is_none() {
+                let msg = format!("Serveur inconnu : `{}`", server);
+                return Ok(self.client.reply(post, &msg)?);
+            }
+            if server == self.server && channel == post.channel_id {
+                return Ok(self.client.reply(post, "Un pont vers soi-même ? Non.")?);
+            }
+
+            self.db
+                .add(&self.server, &post.channel_id, server, channel)?;
+            return Ok(self.client.reaction(post, "ok_hand")?);
+        }
+
+        if let Some(captures) = self.match_del.captures(message) {
+            let id = captures.get(1).unwrap().as_str().parse().unwrap_or(0);
+            self.db.del(&self.server, id)?;
+            return Ok(self.client.reaction(post, "ok_hand")?);
+        }
+
+        Ok(self.client.reply(post, "L'a pô compris.")?)
+    }
+
+    fn mirror(&self, post: &Post) -> Result {
+        // posts the bot mirrored itself must never be mirrored again, even when
+        // seen through another account or another connection.
+        if self.network.echoes.exists(&post.message)
+            || !self
+                .network
+                .links
+                .lock()
+                .unwrap()
+                .get(&self.server, &post.id)
+                .is_empty()
+        {
+            return Ok(());
+        }
+
+        let bridges = self.db.find(&self.server, &post.channel_id)?;
+        if bridges.is_empty() {
+            return Ok(());
+        }
+
+        let message = self.format(&post.user_id, &post.message);
+        self.network.echoes.set(message.clone(), ECHO_DELAY);
+
+        let source = (self.server.clone(), post.clone());
+        for b in bridges {
+            let (server, channel) =
+                if b.from_server == self.server && b.from_channel == post.channel_id {
+                    (b.to_server, b.to_channel)
+                } else {
+                    (b.from_server, b.from_channel)
+                };
+
+            if let Some(client) = self.network.client(&server) {
+                let copy = client
+                    .create_post(&Post::with_message(&message).nchannel(&channel))?;
+                self.network
+                    .links
+                    .lock()
+                    .unwrap()
+                    .link(&source, &(server, copy));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mirror_edit(&self, edited: &PostEdited) -> Result {
+        if edited.user_id == self.client.my_user_id() {
+            return Ok(());
+        }
+
+        let copies = self
+            .network
+            .links
+            .lock()
+            .unwrap()
+            .get(&self.server, &edited.id);
+        if copies.is_empty() {
+            return Ok(());
+        }
+
+        let message = self.format(&edited.user_id, &edited.message);
+        self.network.echoes.set(message.clone(), ECHO_DELAY);
+        for (server, copy) in copies {
+            if let Some(client) = self.network.client(&server) {
+                client.edit(&copy, &message)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mirror_reaction(&self, reaction: &Reaction) -> Result {
+        if reaction.user_id == self.client.my_user_id() {
+            return Ok(());
+        }
+
+        let copies = self
+            .network
+            .links
+            .lock()
+            .unwrap()
+            .get(&self.server, &reaction.post_id);
+        for (server, copy) in copies {
+            if let Some(client) = self.network.client(&server) {
+                client.reaction(&copy, &reaction.emoji_name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<C, D> Handler for Bridge<C, D>
+where
+    C: client::Sender + client::Editor + client::Getter + Clone,
+    D: db::Bridge,
+{
+    type Data = Event;
+
+    fn name(&self) -> String {
+        "bridge".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(format!(
+            "```
+Mirror messages, edits and reactions between two channels, on this server or another one managed by the bot.
+
+This server is `{}`.
+
+!bridge list
+!bridge add <server> <channel id> # link this channel to channel id on server
+!bridge del <id>
+```",
+            self.server
+        ))
+    }
+
+    fn handle(&self, event: &Event) -> Result {
+        match event {
+            Event::Post(post) => {
+                if post.message.starts_with("!bridge") {
+                    self.handle_command(post)
+                } else {
+                    self.mirror(post)
+                }
+            }
+            Event::PostEdited(edited) => self.mirror_edit(edited),
+            Event::Reaction(reaction) => self.mirror_reaction(reaction),
+            _ => Ok(()),
+        }
+    }
+}
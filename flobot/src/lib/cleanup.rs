@@ -0,0 +1,85 @@
+use flobot_lib::client;
+use flobot_lib::handler::{Handler, Result};
+use flobot_lib::models::{Event, Post, PostDeleted};
+use regex::Regex;
+
+/// Most bot posts a single purge deletes.
+const MAX_PURGE: usize = 100;
+
+/// Cleanup deletes the answers of the bot when the post they answer is deleted, and
+/// lets admins purge recent bot posts from a channel.
+pub struct Cleanup<C> {
+    client: C,
+    /// usernames allowed to purge.
+    admins: Vec<String>,
+    match_purge: Regex,
+}
+
+impl<C> Cleanup<C>
+where
+    C: client::Sender + client::Getter + client::Recall,
+{
+    pub fn new(client: C, admins: Vec<String>) -> Self {
+        Self {
+            client,
+            admins,
+            match_purge: Regex::new(r"^!bot[\s]+purge[\s]+([0-9]+)[\s]*$").unwrap(),
+        }
+    }
+
+    fn delete_all(&self, ids: &[String]) -> Result {
+        for id in ids.iter() {
+            self.client.delete(id)?;
+        }
+        Ok(())
+    }
+
+    fn deleted(&self, deleted: &PostDeleted) -> Result {
+        self.delete_all(&self.client.answers(&deleted.id))
+    }
+
+    fn purge(&self, post: &Post) -> Result {
+        let count = match self.match_purge.captures(&post.message) {
+            Some(captures) => captures.get(1).unwrap().as_str(),
+            None => return Ok(()),
+        };
+
+        if !client::is_admin(&self.client, &self.admins, post) {
+            return Ok(self.client.reply(post, "réservé aux admins du bot.")?);
+        }
+
+        let count = count.parse().unwrap_or(MAX_PURGE).min(MAX_PURGE);
+        self.delete_all(&self.client.recent(&post.channel_id, count))?;
+        Ok(self.client.reaction(post, "ok_hand")?)
+    }
+}
+
+impl<C> Handler for Cleanup<C>
+where
+    C: client::Sender + client::Getter + client::Recall,
+{
+    type Data = Event;
+
+    fn name(&self) -> String {
+        "cleanup".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(format!(
+            "```
+Deleting a message deletes the answers of the bot too.
+
+!bot purge <n> # delete the last n messages of the bot in this channel, {} at most. Admins only.
+```",
+            MAX_PURGE
+        ))
+    }
+
+    fn handle(&self, event: &Event) -> Result {
+        match event {
+            Event::PostDeleted(deleted) => self.deleted(deleted),
+            Event::Post(post) => self.purge(post),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod models;
+pub mod namespace;
 use diesel::Connection;
 use std::convert::From;
 
@@ -60,6 +61,8 @@ pub trait Edits {
     ) -> Result<Option<business_models::Edit>>;
     fn del_team(&self, team_id: &str, edit: &str) -> Result<()>;
     fn add_team(&self, team_id: &str, edit: &str, replace: &str) -> Result<()>;
+    /// replace edit with the file found at url.
+    fn add_team_file(&self, team_id: &str, edit: &str, url: &str) -> Result<()>;
 }
 
 pub trait Joke {
@@ -105,6 +108,38 @@ pub trait SMS {
     ) -> Result<Vec<(business_models::SMSPrepare, business_models::SMSContact)>>;
 }
 
+/// Bridges link two channels, possibly on different servers. They are not
+/// namespaced: server names are part of each bridge.
+pub trait Bridge {
+    /// bridges having server and channel on any side.
+    fn find(&self, server: &str, channel: &str)
+        -> Result<Vec<business_models::Bridge>>;
+    /// bridges having server on any side.
+    fn list(&self, server: &str) -> Result<Vec<business_models::Bridge>>;
+    fn add(
+        &self,
+        from_server: &str,
+        from_channel: &str,
+        to_server: &str,
+        to_channel: &str,
+    ) -> Result<()>;
+    /// delete bridge id only if it has server on any side.
+    fn del(&self, server: &str, id: i32) -> Result<()>;
+}
+
+/// Audit keeps track of state-changing commands: who did what, and how it went.
+pub trait Audit {
+    fn record(&self, entry: &business_models::NewAudit) -> Result<()>;
+    /// latest entries first, limit at most, filtered by module and user if given.
+    fn entries(
+        &self,
+        team_id: &str,
+        module: Option<&str>,
+        user_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<business_models::Audit>>;
+}
+
 pub fn conn(db_url: &str) -> DatabaseConnection {
     return DatabaseConnection::establish(db_url).expect("db connection");
 }
@@ -1,4 +1,6 @@
+use crate::db::schema::audit;
 use crate::db::schema::blague;
+use crate::db::schema::bridge;
 use crate::db::schema::edits;
 use crate::db::schema::sms_contact;
 use crate::db::schema::sms_prepare;
@@ -31,6 +33,15 @@ pub struct NewJoke<'a> {
     pub text: &'a str,
 }
 
+#[derive(Insertable)]
+#[table_name = "bridge"]
+pub struct NewBridge<'a> {
+    pub from_server: &'a str,
+    pub from_channel: &'a str,
+    pub to_server: &'a str,
+    pub to_channel: &'a str,
+}
+
 #[derive(Insertable)]
 #[table_name = "sms_contact"]
 pub struct NewSMSContact<'a> {
@@ -97,3 +108,36 @@ pub struct SMSPrepare {
     pub name: String,
     pub text: String,
 }
+
+#[derive(Debug, Queryable)]
+pub struct Bridge {
+    pub id: i32,
+    pub from_server: String,
+    pub from_channel: String,
+    pub to_server: String,
+    pub to_channel: String,
+}
+
+#[derive(Insertable, Clone, Copy)]
+#[table_name = "audit"]
+pub struct NewAudit<'a> {
+    pub at_unixts: i64,
+    pub team_id: &'a str,
+    pub channel_id: &'a str,
+    pub user_id: &'a str,
+    pub module: &'a str,
+    pub command: &'a str,
+    pub outcome: &'a str,
+}
+
+#[derive(Debug, Queryable)]
+pub struct Audit {
+    pub id: i32,
+    pub at_unixts: i64,
+    pub team_id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    pub module: String,
+    pub command: String,
+    pub outcome: String,
+}
@@ -0,0 +1,181 @@
+use crate::db::models::{Audit, Edit, Joke, NewAudit, SMSContact, SMSPrepare, Trigger};
+use crate::db::Result;
+use std::sync::Arc;
+
+/// Namespaced prefixes every team id with a namespace before handing it to the
+/// wrapped database, so several servers can share the same tables without their
+/// team ids colliding. An empty namespace leaves team ids untouched.
+///
+/// # Example
+///
+/// ```rust
+/// # fn main() {
+/// # use diesel::prelude::*;
+/// # use diesel::SqliteConnection;
+/// # use flobot::db::namespace::Namespaced;
+/// # use flobot::db::sqlite::Sqlite;
+/// # use flobot::db::Joke;
+/// # use std::sync::Arc;
+/// # let conn = SqliteConnection::establish(":memory:").unwrap();
+/// # diesel_migrations::run_pending_migrations(&conn).unwrap();
+/// let db = Arc::new(Sqlite::new(conn));
+/// let one = Namespaced::new(db.clone(), "one");
+/// let two = Namespaced::new(db.clone(), "two");
+///
+/// one.add("team", "a joke").unwrap();
+/// assert_eq!(1, one.count("team").unwrap());
+/// assert_eq!(0, two.count("team").unwrap());
+/// assert_eq!(1, db.count("one:team").unwrap());
+/// # }
+/// ```
+pub struct Namespaced<D> {
+    db: Arc<D>,
+    namespace: String,
+}
+
+impl<D> Namespaced<D> {
+    pub fn new(db: Arc<D>, namespace: &str) -> Self {
+        Self {
+            db,
+            namespace: namespace.to_string(),
+        }
+    }
+
+    fn team(&self, team_id: &str) -> String {
+        if self.namespace.is_empty() {
+            team_id.to_string()
+        } else {
+            format!("{}:{}", self.namespace, team_id)
+        }
+    }
+}
+
+impl<D: crate::db::Trigger> crate::db::Trigger for Namespaced<D> {
+    fn list(&self, team_id: &str) -> Result<Vec<Trigger>> {
+        self.db.list(&self.team(team_id))
+    }
+
+    fn search(&self, team_id: &str) -> Result<Vec<Trigger>> {
+        self.db.search(&self.team(team_id))
+    }
+
+    fn add_text(&self, team_id: &str, trigger: &str, text: &str) -> Result<()> {
+        self.db.add_text(&self.team(team_id), trigger, text)
+    }
+
+    fn add_emoji(&self, team_id: &str, trigger: &str, emoji: &str) -> Result<()> {
+        self.db.add_emoji(&self.team(team_id), trigger, emoji)
+    }
+
+    fn del(&self, team_id: &str, trigger: &str) -> Result<()> {
+        self.db.del(&self.team(team_id), trigger)
+    }
+}
+
+impl<D: crate::db::Edits> crate::db::Edits for Namespaced<D> {
+    fn list(&self, team_id: &str) -> Result<Vec<Edit>> {
+        self.db.list(&self.team(team_id))
+    }
+
+    fn find(&self, user_id: &str, team_id: &str, edit: &str) -> Result<Option<Edit>> {
+        self.db.find(user_id, &self.team(team_id), edit)
+    }
+
+    fn del_team(&self, team_id: &str, edit: &str) -> Result<()> {
+        self.db.del_team(&self.team(team_id), edit)
+    }
+
+    fn add_team(&self, team_id: &str, edit: &str, replace: &str) -> Result<()> {
+        self.db.add_team(&self.team(team_id), edit, replace)
+    }
+
+    fn add_team_file(&self, team_id: &str, edit: &str, url: &str) -> Result<()> {
+        self.db.add_team_file(&self.team(team_id), edit, url)
+    }
+}
+
+impl<D: crate::db::Joke> crate::db::Joke for Namespaced<D> {
+    fn pick(&self, team_id: &str, relnum: u64) -> Result<Option<Joke>> {
+        self.db.pick(&self.team(team_id), relnum)
+    }
+
+    fn count(&self, team_id: &str) -> Result<u64> {
+        self.db.count(&self.team(team_id))
+    }
+
+    fn list(&self, team_id: &str) -> Result<Vec<Joke>> {
+        self.db.list(&self.team(team_id))
+    }
+
+    fn del(&self, team_id: &str, id: i32) -> Result<()> {
+        self.db.del(&self.team(team_id), id)
+    }
+
+    fn add(&self, team_id: &str, text: &str) -> Result<()> {
+        self.db.add(&self.team(team_id), text)
+    }
+}
+
+impl<D: crate::db::SMS> crate::db::SMS for Namespaced<D> {
+    fn set_contact(
+        &self,
+        team_id: &str,
+        name: &str,
+        number: &str,
+    ) -> Result<SMSContact> {
+        self.db.set_contact(&self.team(team_id), name, number)
+    }
+
+    fn set_prepare(
+        &self,
+        team_id: &str,
+        contact_id: &i32,
+        trigname: &str,
+        name: &str,
+        text: &str,
+    ) -> Result<SMSPrepare> {
+        self.db
+            .set_prepare(&self.team(team_id), contact_id, trigname, name, text)
+    }
+
+    fn get_contact(
+        &self,
+        team_id: &str,
+        name: Option<&str>,
+        id: Option<&i32>,
+    ) -> Result<Option<SMSContact>> {
+        self.db.get_contact(&self.team(team_id), name, id)
+    }
+
+    fn get_prepare(&self, team_id: &str, trigname: &str) -> Result<Option<SMSPrepare>> {
+        self.db.get_prepare(&self.team(team_id), trigname)
+    }
+
+    fn list_contacts(&self, team_id: &str) -> Result<Vec<SMSContact>> {
+        self.db.list_contacts(&self.team(team_id))
+    }
+
+    fn list_prepare(&self, team_id: &str) -> Result<Vec<(SMSPrepare, SMSContact)>> {
+        self.db.list_prepare(&self.team(team_id))
+    }
+}
+
+impl<D: crate::db::Audit> crate::db::Audit for Namespaced<D> {
+    fn record(&self, entry: &NewAudit) -> Result<()> {
+        let team_id = self.team(entry.team_id);
+        self.db.record(&NewAudit {
+            team_id: &team_id,
+            ..*entry
+        })
+    }
+
+    fn entries(
+        &self,
+        team_id: &str,
+        module: Option<&str>,
+        user_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Audit>> {
+        self.db.entries(&self.team(team_id), module, user_id, limit)
+    }
+}
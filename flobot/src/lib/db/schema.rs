@@ -1,3 +1,26 @@
+table! {
+    audit (id) {
+        id -> Integer,
+        at_unixts -> BigInt,
+        team_id -> Text,
+        channel_id -> Text,
+        user_id -> Text,
+        module -> Text,
+        command -> Text,
+        outcome -> Text,
+    }
+}
+
+table! {
+    bridge (id) {
+        id -> Integer,
+        from_server -> Text,
+        from_channel -> Text,
+        to_server -> Text,
+        to_channel -> Text,
+    }
+}
+
 table! {
     blague (id) {
         id -> Integer,
@@ -50,4 +73,12 @@ table! {
 
 joinable!(sms_prepare -> sms_contact (sms_contact_id));
 
-allow_tables_to_appear_in_same_query!(blague, edits, sms_contact, sms_prepare, trigger,);
+allow_tables_to_appear_in_same_query!(
+    audit,
+    blague,
+    bridge,
+    edits,
+    sms_contact,
+    sms_prepare,
+    trigger,
+);
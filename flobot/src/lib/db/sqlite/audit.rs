@@ -0,0 +1,69 @@
+use crate::db::models::{Audit, NewAudit};
+use crate::db::schema::audit::dsl as table;
+use crate::db::Result;
+use diesel::prelude::*;
+
+impl crate::db::Audit for super::Sqlite {
+    fn record(&self, entry: &NewAudit) -> Result<()> {
+        let _ = diesel::insert_into(table::audit)
+            .values(entry)
+            .execute(&*self.db.lock().unwrap())?;
+        Ok(())
+    }
+
+    /// # Example
+    ///
+    /// ```rust
+    /// # fn main() {
+    /// # use diesel::prelude::*;
+    /// # use diesel::SqliteConnection;
+    /// # use flobot::db::models::NewAudit;
+    /// # use flobot::db::sqlite::Sqlite;
+    /// # use flobot::db::Audit;
+    /// # let conn = SqliteConnection::establish(":memory:").unwrap();
+    /// # diesel_migrations::run_pending_migrations(&conn).unwrap();
+    /// # let s = Sqlite::new(conn);
+    /// let entry = NewAudit {
+    ///     at_unixts: 1,
+    ///     team_id: "team",
+    ///     channel_id: "town",
+    ///     user_id: "alice",
+    ///     module: "trigger",
+    ///     command: "!trigger del \"hello\"",
+    ///     outcome: "ok",
+    /// };
+    /// s.record(&entry).unwrap();
+    /// s.record(&NewAudit { at_unixts: 2, module: "joke", ..entry }).unwrap();
+    /// s.record(&NewAudit { at_unixts: 3, user_id: "bob", ..entry }).unwrap();
+    /// s.record(&NewAudit { team_id: "other", ..entry }).unwrap();
+    ///
+    /// let all = s.entries("team", None, None, 10).unwrap();
+    /// assert_eq!(vec![3, 2, 1], all.iter().map(|a| a.at_unixts).collect::<Vec<i64>>());
+    /// assert_eq!(2, s.entries("team", Some("trigger"), None, 10).unwrap().len());
+    /// let alice = s.entries("team", Some("trigger"), Some("alice"), 10).unwrap();
+    /// assert_eq!(1, alice.len());
+    /// assert_eq!("ok", alice[0].outcome);
+    /// assert_eq!(2, s.entries("team", None, None, 2).unwrap().len());
+    /// # }
+    /// ```
+    fn entries(
+        &self,
+        team_id: &str,
+        module: Option<&str>,
+        user_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Audit>> {
+        let mut query = table::audit.filter(table::team_id.eq(team_id)).into_boxed();
+        if let Some(module) = module {
+            query = query.filter(table::module.eq(module));
+        }
+        if let Some(user_id) = user_id {
+            query = query.filter(table::user_id.eq(user_id));
+        }
+        Ok(query
+            .order_by(table::at_unixts.desc())
+            .then_order_by(table::id.desc())
+            .limit(limit)
+            .load::<Audit>(&*self.db.lock().unwrap())?)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::db::models::{Bridge, NewBridge};
+use crate::db::schema::bridge::dsl as table;
+use crate::db::Result;
+use diesel::prelude::*;
+
+impl crate::db::Bridge for super::Sqlite {
+    fn find(&self, server: &str, channel: &str) -> Result<Vec<Bridge>> {
+        Ok(table::bridge
+            .filter(
+                (table::from_server
+                    .eq(server)
+                    .and(table::from_channel.eq(channel)))
+                .or(table::to_server
+                    .eq(server)
+                    .and(table::to_channel.eq(channel))),
+            )
+            .load::<Bridge>(&*self.db.lock().unwrap())?)
+    }
+
+    fn list(&self, server: &str) -> Result<Vec<Bridge>> {
+        Ok(table::bridge
+            .filter(
+                table::from_server
+                    .eq(server)
+                    .or(table::to_server.eq(server)),
+            )
+            .order_by(table::id.asc())
+            .load::<Bridge>(&*self.db.lock().unwrap())?)
+    }
+
+    /// # Example
+    ///
+    /// ```rust
+    /// # fn main() {
+    /// # use diesel::prelude::*;
+    /// # use diesel::SqliteConnection;
+    /// # use flobot::db::sqlite::Sqlite;
+    /// # use flobot::db::Bridge;
+    /// # let conn = SqliteConnection::establish(":memory:").unwrap();
+    /// # diesel_migrations::run_pending_migrations(&conn).unwrap();
+    /// # let s = Sqlite::new(conn);
+    /// s.add("home", "town", "work", "square").unwrap();
+    /// assert_eq!(1, s.find("work", "square").unwrap().len());
+    /// assert_eq!(0, s.find("work", "town").unwrap().len());
+    ///
+    /// let id = s.list("home").unwrap()[0].id;
+    /// s.del("elsewhere", id).unwrap();
+    /// assert_eq!(1, s.list("home").unwrap().len());
+    /// s.del("work", id).unwrap();
+    /// assert_eq!(0, s.list("home").unwrap().len());
+    /// # }
+    /// ```
+    fn add(
+        &self,
+        from_server: &str,
+        from_channel: &str,
+        to_server: &str,
+        to_channel: &str,
+    ) -> Result<()> {
+        let new_bridge = NewBridge {
+            from_server,
+            from_channel,
+            to_server,
+            to_channel,
+        };
+
+        let _ = diesel::insert_into(table::bridge)
+            .values(&new_bridge)
+            .execute(&*self.db.lock().unwrap())?;
+        Ok(())
+    }
+
+    fn del(&self, server: &str, id: i32) -> Result<()> {
+        let filter = table::bridge.filter(
+            table::id.eq(id).and(
+                table::from_server
+                    .eq(server)
+                    .or(table::to_server.eq(server)),
+            ),
+        );
+        let _ = diesel::delete(filter).execute(&*self.db.lock().unwrap())?;
+        Ok(())
+    }
+}
@@ -74,4 +74,35 @@ impl crate::db::Edits for super::Sqlite {
             .execute(&*self.db.lock().unwrap())?;
         Ok(())
     }
+
+    /// ```rust
+    /// # fn main() {
+    /// # use diesel::prelude::*;
+    /// # use diesel::SqliteConnection;
+    /// # use flobot::db::sqlite::Sqlite;
+    /// # use flobot::db::Edits;
+    /// # let conn = SqliteConnection::establish(":memory:").unwrap();
+    /// # diesel_migrations::run_pending_migrations(&conn).unwrap();
+    /// # let s = Sqlite::new(conn);
+    /// s.add_team_file("team", "cat", "https://example.com/cat.png").unwrap();
+    /// let e = s.find("user", "team", "cat").unwrap().unwrap();
+    ///
+    /// assert_eq!(None, e.replace_with_text);
+    /// assert_eq!("https://example.com/cat.png", e.replace_with_file.unwrap());
+    /// # }
+    /// ```
+    fn add_team_file(&self, team_id: &str, edit: &str, url: &str) -> Result<()> {
+        let edit_ = NewEdit {
+            edit,
+            replace_with_text: None,
+            replace_with_file: Some(url),
+            team_id: Some(team_id),
+            user_id: None,
+        };
+
+        let _ = diesel::insert_into(table::edits)
+            .values(&edit_)
+            .execute(&*self.db.lock().unwrap())?;
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use diesel::SqliteConnection;
+use diesel::{Connection, SqliteConnection};
 use std::sync::Mutex;
 
 pub struct Sqlite {
@@ -9,6 +9,12 @@ impl Sqlite {
     pub fn new(db: SqliteConnection) -> Self {
         Self { db: Mutex::new(db) }
     }
+
+    /// Check the database answers queries.
+    pub fn ping(&self) -> crate::db::Result<()> {
+        self.db.lock().unwrap().execute("SELECT 1")?;
+        Ok(())
+    }
 }
 
 /// new is a shortcut for Sqlite::new
@@ -16,6 +22,8 @@ pub fn new(db: SqliteConnection) -> Sqlite {
     Sqlite::new(db)
 }
 
+mod audit;
+mod bridge;
 mod edits;
 mod joke;
 mod sms;
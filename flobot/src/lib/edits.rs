@@ -1,3 +1,4 @@
+use crate::audit;
 use crate::db;
 use flobot_lib::client;
 use flobot_lib::handler::{Handler, Result};
@@ -10,6 +11,7 @@ pub struct Edit<C, E> {
     match_list: Regex,
     match_del: Regex,
     match_add: Regex,
+    match_add_file: Regex,
     match_edit: Regex,
     client: C,
     db: Arc<E>,
@@ -18,13 +20,15 @@ pub struct Edit<C, E> {
 impl<C, E> Edit<C, E>
 where
     C: client::Editor + client::Sender,
-    E: db::Edits,
+    E: db::Edits + db::Audit,
 {
     pub fn new(db: Arc<E>, client: C) -> Self {
         Self {
             match_list: Regex::new("^!edits list.*$").unwrap(),
             match_del: Regex::new("^!edits del \"(.+)\".*").unwrap(),
             match_add: Regex::new("^!edits add \"(.+)\" \"(.+)\".*").unwrap(),
+            match_add_file: Regex::new("^!edits file \"(.+)\" \"(https?://.+)\".*")
+                .unwrap(),
             match_edit: Regex::new("^!e (.+)").unwrap(),
             db,
             client,
@@ -38,8 +42,9 @@ where
             Some(edit) => {
                 if edit.replace_with_text.is_some() {
                     self.client.edit(&post, &edit.replace_with_text.unwrap())?;
-                } else if edit.replace_with_file.is_some() {
-                    // unimplemented
+                } else if let Some(url) = edit.replace_with_file {
+                    let file = self.client.download(&url)?;
+                    self.client.edit_with_files(post, "", &[file])?;
                 }
             }
             _ => {}
@@ -49,7 +54,9 @@ where
     }
 
     fn handle_del_team(&self, post: &Post, captured: &str) -> Result {
-        let _ = self.db.del_team(&post.team_id, captured)?;
+        let res = self.db.del_team(&post.team_id, captured);
+        audit::record(&*self.db, post, "edits", &res);
+        res?;
         Ok(self.client.reaction(post, "ok_hand")?)
     }
 
@@ -58,13 +65,28 @@ where
             return Ok(self.client.reply(post, "aha, aha… il est boubourse :3")?);
         }
 
-        if post.team_id == "" {
+        if post.channel_kind.is_direct() {
             return Ok(self
                 .client
                 .reply(post, "je sais pas encore faire des edits privés :/")?);
         }
 
-        let _ = self.db.add_team(&post.team_id, word, replace)?;
+        let res = self.db.add_team(&post.team_id, word, replace);
+        audit::record(&*self.db, post, "edits", &res);
+        res?;
+        Ok(self.client.reaction(post, "ok_hand")?)
+    }
+
+    fn handle_add_file(&self, post: &Post, word: &str, url: &str) -> Result {
+        if post.channel_kind.is_direct() {
+            return Ok(self
+                .client
+                .reply(post, "je sais pas encore faire des edits privés :/")?);
+        }
+
+        let res = self.db.add_team_file(&post.team_id, word, url);
+        audit::record(&*self.db, post, "edits", &res);
+        res?;
         Ok(self.client.reaction(post, "ok_hand")?)
     }
 
@@ -72,7 +94,7 @@ where
         let res = self.db.list(&post.team_id)?;
 
         if res.len() == 0 {
-            return Ok(self.client.reply(post, "yen a pô :GE:")?);
+            return Ok(self.client.ephemeral(post, "yen a pô :GE:")?);
         }
 
         let mut out = String::from("Remplacements disponibles:\n");
@@ -80,11 +102,14 @@ where
             out.push_str(&format!(
                 " * `{}` -> {}\n",
                 edit_.edit,
-                edit_.replace_with_text.unwrap_or("".to_string())
+                edit_
+                    .replace_with_text
+                    .or(edit_.replace_with_file)
+                    .unwrap_or("".to_string())
             ));
         }
 
-        Ok(self.client.reply(post, &out)?)
+        Ok(self.client.ephemeral(post, &out)?)
     }
 
     fn handle_post(&self, post: &Post) -> Result {
@@ -108,6 +133,14 @@ where
             None => {}
         };
 
+        if let Some(captures) = self.match_add_file.captures(&message) {
+            return self.handle_add_file(
+                post,
+                captures.get(1).unwrap().as_str(),
+                captures.get(2).unwrap().as_str(),
+            );
+        }
+
         match self.match_list.captures(&message) {
             Some(_captures) => return self.handle_list(post),
             None => {}
@@ -127,7 +160,7 @@ where
 impl<C, E> Handler for Edit<C, E>
 where
     C: client::Sender + client::Editor,
-    E: db::Edits,
+    E: db::Edits + db::Audit,
 {
     type Data = Post;
 
@@ -139,6 +172,7 @@ where
             "```
 !edits list
 !edits add \"edit\" \"replace\"
+!edits file \"edit\" \"https://picture.url\" # replace with the picture
 !edits del \"edit\"
 !e edit
 ```"
@@ -1,6 +1,8 @@
+use crate::audit;
 use crate::db::Joke as DB;
 use flobot_lib::client;
 use flobot_lib::handler::Handler as BotHandler;
+use flobot_lib::metrics::Metric;
 use flobot_lib::models::Post;
 use rand::Rng;
 use regex::Regex;
@@ -29,6 +31,8 @@ impl From<reqwest::Error> for Error {
 }
 
 pub trait Random {
+    /// Short name of the provider, for metrics.
+    fn name(&self) -> String;
     fn random(&self, team_id: &str) -> Result;
 }
 
@@ -43,6 +47,11 @@ impl From<crate::db::Error> for Error {
 
 pub type Provider = Arc<dyn Random + Send + Sync>;
 
+const PROVIDER_CALLS: Metric = Metric::counter(
+    "flobot_joke_provider_calls_total",
+    "Jokes asked to a provider, by provider and result.",
+);
+
 pub struct SelectProvider {
     remotes: Vec<Provider>,
 }
@@ -62,15 +71,18 @@ impl SelectProvider {
 }
 
 impl Random for SelectProvider {
+    fn name(&self) -> String {
+        "select".into()
+    }
+
     fn random(&self, team_id: &str) -> Result {
         let l = self.remotes.len();
         let mut remote_n = rand::thread_rng().gen_range(0..l);
         for _i in 0..l {
-            let res = self
-                .remotes
-                .get(remote_n) // [0, l) [incl, excl)
-                .unwrap()
-                .random(team_id);
+            let remote = self.remotes.get(remote_n).unwrap(); // [0, l) [incl, excl)
+            let res = remote.random(team_id);
+            let result = if res.is_ok() { "success" } else { "failure" };
+            PROVIDER_CALLS.inc(&[("provider", &remote.name()), ("result", result)]);
 
             if res.is_ok() {
                 return res;
@@ -100,6 +112,10 @@ impl<D> Random for ProviderSQLite<D>
 where
     D: crate::db::Joke,
 {
+    fn name(&self) -> String {
+        "sqlite".into()
+    }
+
     fn random(&self, team_id: &str) -> Result {
         let l = self.db.count(team_id)?;
         if l < 1 {
@@ -155,6 +171,10 @@ impl ProviderBadJokes {
 }
 
 impl Random for ProviderBadJokes {
+    fn name(&self) -> String {
+        "badjokes".into()
+    }
+
     fn random(&self, _team_id: &str) -> Result {
         let q = self
             .c
@@ -202,6 +222,10 @@ impl ProviderBlaguesAPI {
 }
 
 impl Random for ProviderBlaguesAPI {
+    fn name(&self) -> String {
+        "blaguesapi".into()
+    }
+
     fn random(&self, _team_id: &str) -> Result {
         let joke: BlaguesAPIResponse = self
             .client
@@ -217,6 +241,10 @@ pub struct ProviderFile {
 }
 
 impl Random for ProviderFile {
+    fn name(&self) -> String {
+        "file".into()
+    }
+
     fn random(&self, _team_id: &str) -> Result {
         let rnd = rand::random::<usize>() % self.urls.len();
         Ok(self.urls[rnd].clone())
@@ -256,7 +284,7 @@ impl<R, S, C> Handler<R, S, C> {
 impl<R, C, S> BotHandler for Handler<R, S, C>
 where
     C: client::Sender,
-    S: DB,
+    S: DB + crate::db::Audit,
     R: Random,
 {
     type Data = Post;
@@ -268,7 +296,7 @@ where
         Some(
             "```
 !joke # quick, a joke, now!
-!joke <register a joke>
+!joke <register a joke> # a lone picture url is posted as the picture
 !joke list
 !joke del <num>
 ```"
@@ -281,7 +309,10 @@ where
 
         if msg == "!joke" {
             let joke = self.remotes.random(&post.team_id)?;
-            return Ok(self.client.post(&post.nmessage(&joke))?);
+            return Ok(client::post_or_picture(
+                &self.client,
+                &post.nmessage(&joke),
+            )?);
         } else if msg == "!joke list" {
             let jokes = self.store.list(&post.team_id)?;
             let mut rep = String::from("Available jokes:\n");
@@ -289,14 +320,16 @@ where
                 rep.push_str(&format!(" * {}: {}\n", joke.id, &joke.text));
             }
 
-            return Ok(self.client.post(&post.nmessage(&rep))?);
+            return Ok(self.client.ephemeral(post, &rep)?);
         }
 
         match self.match_del.captures(msg) {
             Some(captures) => {
                 match captures.get(1).unwrap().as_str().trim().parse() {
                     Ok(num) => {
-                        self.store.del(&post.team_id, num)?;
+                        let res = self.store.del(&post.team_id, num);
+                        audit::record(&*self.store, post, "joke", &res);
+                        res?;
                         return Ok(self.client.reaction(post, "ok_hand")?);
                     }
                     Err(e) => {
@@ -317,7 +350,9 @@ where
                             .client
                             .reply(post, "too long: max 300 chars")?);
                     }
-                    self.store.add(&post.team_id, joke)?;
+                    let res = self.store.add(&post.team_id, joke);
+                    audit::record(&*self.store, post, "joke", &res);
+                    res?;
                     return Ok(self.client.reaction(post, "ok_hand")?);
                 }
                 None => {
@@ -1,15 +1,22 @@
 #[macro_use]
 extern crate diesel;
 
+pub mod audit;
+pub mod bridge;
+pub mod cleanup;
 pub mod db;
 pub mod edits;
 pub mod joke;
+pub mod lifecycle;
 pub mod pinterest;
 pub mod sms;
+pub mod systemd;
 pub mod trigger;
+pub mod upgrade;
 pub mod weather;
 pub mod werewolf;
 pub mod werewolf_game;
+pub mod www;
 
 use flobot_lib::handler::Error as HandlerError;
 
@@ -0,0 +1,226 @@
+//! Threads of the bot, watched so a thread ending on its own stops the bot, and so
+//! the bot waits for every thread on shutdown, a while at most.
+
+use flobot_lib::panic;
+use flobot_lib::{error, info};
+use std::collections::BTreeSet;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+enum Message {
+    Stop(String),
+    Ended(String, Result<(), String>),
+}
+
+/// Ask the bot to stop, from any thread.
+#[derive(Clone)]
+pub struct Stopper {
+    sender: Sender<Message>,
+}
+
+impl Stopper {
+    pub fn stop(&self, reason: &str) {
+        let _ = self.sender.send(Message::Stop(reason.to_string()));
+    }
+}
+
+/// Supervisor spawns the threads of the bot and waits for them.
+///
+/// ```rust
+/// # use flobot::lifecycle::Supervisor;
+/// # use std::time::Duration;
+/// let mut supervisor = Supervisor::new();
+/// supervisor.spawn("fine", || Ok(()));
+/// supervisor.spawn("broken", || Err("no more events".to_string()));
+/// supervisor.spawn("hung", || loop {
+///     std::thread::sleep(Duration::from_secs(1));
+/// });
+///
+/// let reason = supervisor.wait_stop();
+/// assert!(reason.contains("ended"), "{}", reason);
+/// assert!(!supervisor.join(Duration::from_millis(100)));
+/// assert!(supervisor.failed());
+/// ```
+pub struct Supervisor {
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+    running: BTreeSet<String>,
+    failed: bool,
+    stopping: bool,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver,
+            running: BTreeSet::new(),
+            failed: false,
+            stopping: false,
+        }
+    }
+
+    pub fn stopper(&self) -> Stopper {
+        Stopper {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Run f in a thread named name. Returning, with an error or not, or
+    /// panicking before the bot is stopping is a failure.
+    pub fn spawn<F>(&mut self, name: &str, f: F)
+    where
+        F: FnOnce() -> Result<(), String> + Send + 'static,
+    {
+        let sender = self.sender.clone();
+        let thread_name = name.to_string();
+        self.running.insert(thread_name.clone());
+        thread::spawn(move || {
+            info!("thread {} started", thread_name);
+            let res = panic::catch(f).unwrap_or_else(|p| Err(p.message));
+            let _ = sender.send(Message::Ended(thread_name, res));
+        });
+    }
+
+    fn ended(&mut self, name: String, res: Result<(), String>) {
+        self.running.remove(&name);
+        match res {
+            Ok(()) if self.stopping => info!("thread {} ended", name),
+            Ok(()) => {
+                error!("thread {} ended before the bot stopped", name);
+                self.failed = true;
+            }
+            Err(e) => {
+                error!("thread {} failed: {}", name, e);
+                self.failed = true;
+            }
+        }
+    }
+
+    /// Block until the bot is asked to stop or a thread ends. Returns why.
+    pub fn wait_stop(&mut self) -> String {
+        let reason = match self.receiver.recv() {
+            Ok(Message::Stop(reason)) => reason,
+            Ok(Message::Ended(name, res)) => {
+                let reason = format!("thread {} ended", name);
+                self.ended(name, res);
+                reason
+            }
+            // the supervisor keeps a sender: never happens.
+            Err(e) => e.to_string(),
+        };
+        self.stopping = true;
+        reason
+    }
+
+    /// Wait for every thread to end, timeout at most. Returns whether they all did.
+    pub fn join(&mut self, timeout: Duration) -> bool {
+        self.stopping = true;
+        let deadline = Instant::now() + timeout;
+        while !self.running.is_empty() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(left) {
+                Ok(Message::Ended(name, res)) => self.ended(name, res),
+                Ok(Message::Stop(_)) => {}
+                Err(RecvTimeoutError::Timeout)
+                | Err(RecvTimeoutError::Disconnected) => {
+                    error!("threads still running: {:?}", self.running);
+                    self.failed = true;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether a thread failed, ended too early, or did not end in time.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// A thread running until stopped is set.
+    fn until(supervisor: &mut Supervisor, name: &str, stopped: &Arc<AtomicBool>) {
+        let stopped = stopped.clone();
+        supervisor.spawn(name, move || {
+            while !stopped.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn graceful_stop() {
+        let mut supervisor = Supervisor::new();
+        let stopped = Arc::new(AtomicBool::new(false));
+        until(&mut supervisor, "first", &stopped);
+        until(&mut supervisor, "second", &stopped);
+
+        supervisor.stopper().stop("SIGTERM received");
+        assert_eq!("SIGTERM received", supervisor.wait_stop());
+        stopped.store(true, Ordering::SeqCst);
+        assert!(supervisor.join(Duration::from_secs(5)));
+        assert!(!supervisor.failed());
+    }
+
+    #[test]
+    fn panic_is_a_failure() {
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn("panicking", || panic!("boom"));
+
+        assert_eq!("thread panicking ended", supervisor.wait_stop());
+        assert!(supervisor.join(Duration::from_secs(5)));
+        assert!(supervisor.failed());
+    }
+
+    #[test]
+    fn error_while_stopping_is_a_failure() {
+        let mut supervisor = Supervisor::new();
+        let stopped = Arc::new(AtomicBool::new(false));
+        {
+            let stopped = stopped.clone();
+            supervisor.spawn("failing", move || {
+                while !stopped.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                Err("cannot flush".to_string())
+            });
+        }
+
+        supervisor.stopper().stop("test");
+        supervisor.wait_stop();
+        stopped.store(true, Ordering::SeqCst);
+        assert!(supervisor.join(Duration::from_secs(5)));
+        assert!(supervisor.failed());
+    }
+
+    #[test]
+    fn stops_asked_while_joining_are_ignored() {
+        let mut supervisor = Supervisor::new();
+        let stopped = Arc::new(AtomicBool::new(false));
+        until(&mut supervisor, "worker", &stopped);
+        let stopper = supervisor.stopper();
+
+        stopper.stop("first");
+        assert_eq!("first", supervisor.wait_stop());
+        stopper.stop("second");
+        stopped.store(true, Ordering::SeqCst);
+        assert!(supervisor.join(Duration::from_secs(5)));
+        assert!(!supervisor.failed());
+    }
+}
@@ -2,6 +2,7 @@ use crate::joke::{Error, Random, Result};
 use chrono::{DateTime, Duration, Local};
 use flobot_lib::client::Notifier;
 use flobot_lib::task::{self, ExecIn, Task};
+use flobot_lib::{debug, error, info, trace};
 use reqwest::blocking::Client;
 use serde::Deserialize;
 use std::result::Result as StdResult;
@@ -124,7 +125,7 @@ impl<N: Notifier> Pinterest<N> {
             .send();
 
         if res.is_err() {
-            println!("pinterest: error on authentication: {:?}", res);
+            error!("pinterest: error on authentication: {:?}", res);
             return false;
         }
 
@@ -134,19 +135,24 @@ impl<N: Notifier> Pinterest<N> {
             // V5
             //let mut token = token;
             //token.compute_refresh(true);
-            println!("pinterest access token: {}", &token.data.access_token);
-            println!("pinterest scope: {}", &token.data.scope);
+            flobot_lib::log::secret(&token.data.access_token);
+            info!("pinterest: access token received");
+            debug!("pinterest scope: {}", &token.data.scope);
             *guard = Some(token);
             return true;
         }
 
-        println!("pinterest authenticate error: {:?}", res.err());
+        error!("pinterest authenticate error: {:?}", res.err());
 
         false
     }
 }
 
 impl<N: Notifier> Random for Pinterest<N> {
+    fn name(&self) -> String {
+        "pinterest".into()
+    }
+
     fn random(&self, _team_id: &str) -> Result {
         if let Some(token) = &((*self.token.read().unwrap()).as_ref()) {
             let at = token.data.access_token.clone();
@@ -159,7 +165,7 @@ impl<N: Notifier> Random for Pinterest<N> {
                 .send()?
                 .json::<serde_json::Value>()?;
 
-            println!("bord list: {:?}", val);
+            debug!("bord list: {:?}", val);
 
             if val.get("code").is_none() {
                 let items: &Vec<serde_json::Value> =
@@ -173,7 +179,7 @@ impl<N: Notifier> Random for Pinterest<N> {
                     .send()?
                     .json::<serde_json::Value>()?;
 
-                println!("pin: {:?}", val);
+                trace!("pin: {:?}", val);
             }
         }
 
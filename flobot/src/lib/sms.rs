@@ -1,13 +1,24 @@
+use crate::audit;
 use crate::db;
+use chrono::Utc;
 use flobot_lib::client;
 use flobot_lib::handler::{Error, Handler, Result};
-use flobot_lib::models::Post;
+use flobot_lib::models::{Attachment, ChannelKind, Post};
+use flobot_lib::{debug, error, warn};
 use regex::Regex;
 use reqwest;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::convert::From;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
 
+/// How long a sending waits for its confirmation.
+const CONFIRM_DELAY: Duration = Duration::from_secs(600);
+
+#[derive(Debug)]
 pub enum SMSError {
     CannotSend(String),
 }
@@ -50,6 +61,21 @@ impl Octopush {
     }
 }
 
+/// Sends nothing, used when replaying recorded traffic.
+pub struct Dry;
+
+impl SMSSender for Dry {
+    fn send(
+        &self,
+        _text: &str,
+        _to_number: &str,
+        _from_name: &str,
+    ) -> std::result::Result<(), SMSError> {
+        debug!("sms not sent: dry run");
+        Ok(())
+    }
+}
+
 impl SMSSender for Octopush {
     fn send(
         &self,
@@ -71,14 +97,18 @@ impl SMSSender for Octopush {
             .header("api-login", &self.login)
             .header("api-key", &self.apikey)
             .json(&sms)
-            .send()?;
-
-        match r.status() {
-            reqwest::StatusCode::BAD_REQUEST => {
-                println!("Sms Send 400: {:?}", r.text())
-            }
-            _ => println!("Sms Send: {:?}: {:?}", r.status(), r.text()),
-        };
+            .send()
+            .map_err(|e| {
+                error!("Sms Send: {:?}", e);
+                e
+            })?;
+
+        let status = r.status();
+        if status.is_success() {
+            debug!("Sms Send: {:?}: {:?}", status, r.text());
+        } else {
+            error!("Sms Send: {:?}: {:?}", status, r.text());
+        }
 
         Ok(())
     }
@@ -86,6 +116,24 @@ impl SMSSender for Octopush {
 
 // END IMPLEM
 
+/// A SMS waiting for the confirmation of the user who asked for it.
+#[derive(Serialize, Deserialize)]
+struct Pending {
+    user_id: String,
+    contact: String,
+    number: String,
+    name: String,
+    text: String,
+    /// unix timestamp, in seconds.
+    asked_at: i64,
+}
+
+impl Pending {
+    fn expired(&self) -> bool {
+        Utc::now().timestamp() - self.asked_at >= CONFIRM_DELAY.as_secs() as i64
+    }
+}
+
 pub struct SMS<S, D, C> {
     provider: S,
     db: Arc<D>,
@@ -95,10 +143,15 @@ pub struct SMS<S, D, C> {
     re_send: Regex,
     re_sendn: Regex,
     re_list: Regex,
+    re_confirm: Regex,
+    re_cancel: Regex,
+    /// whether sending waits for a confirmation.
+    confirm: bool,
+    pending: Mutex<HashMap<String, Pending>>,
 }
 
-impl<S: SMSSender, D: db::SMS, C: client::Sender> SMS<S, D, C> {
-    pub fn new(provider: S, db: Arc<D>, client: C) -> Self {
+impl<S: SMSSender, D: db::SMS + db::Audit, C: client::Sender> SMS<S, D, C> {
+    pub fn new(provider: S, db: Arc<D>, client: C, confirm: bool) -> Self {
         Self {
             db: db,
             provider: provider,
@@ -111,11 +164,82 @@ impl<S: SMSSender, D: db::SMS, C: client::Sender> SMS<S, D, C> {
             re_send: Regex::new(r"^!sms[\s]+([a-zA-Z0-9\-_\.]+)[\s]*$").unwrap(),
             re_list: Regex::new(r"^!sms[\s]+list[\s]*$").unwrap(),
             re_sendn: Regex::new(r"^!sms[\s]+send[\s]+([a-zA-Z0-9\-_\.]+)[\s]+([a-zA-Z0-9]+)[\s]+(.*)$").unwrap(),
+            re_confirm: Regex::new(r"^!sms[\s]+confirm[\s]+([a-z0-9]+)[\s]*$").unwrap(),
+            re_cancel: Regex::new(r"^!sms[\s]+cancel[\s]+([a-z0-9]+)[\s]*$").unwrap(),
+            confirm,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send the SMS post asked for, recording how it went.
+    fn send(
+        &self,
+        post: &Post,
+        contact: &str,
+        number: &str,
+        name: &str,
+        text: &str,
+    ) -> Result {
+        let res = self.provider.send(text, number, name);
+        let command = format!("{} → {}, {}: {}", post.message, contact, name, text);
+        audit::record_command(&*self.db, post, "sms", &command, &res);
+        if let Err(e) = res {
+            self.client.reaction(post, "no_entry_sign")?;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Keep the SMS aside and ask the user to confirm sending it.
+    fn ask_confirm(
+        &self,
+        post: &Post,
+        contact: &str,
+        number: &str,
+        name: &str,
+        text: &str,
+    ) -> Result {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, p| !p.expired());
+
+        let token = Uuid::new_v4().to_simple().to_string()[..8].to_string();
+        pending.insert(
+            token.clone(),
+            Pending {
+                user_id: post.user_id.clone(),
+                contact: contact.to_string(),
+                number: number.to_string(),
+                name: name.to_string(),
+                text: text.to_string(),
+                asked_at: Utc::now().timestamp(),
+            },
+        );
+
+        let confirm = Attachment::new("")
+            .field("Contact", contact, true)
+            .field("Intitulé", name, true)
+            .field("Texte", text, false)
+            .button("Envoyer", &format!("!sms confirm {}", token))
+            .button("Annuler", &format!("!sms cancel {}", token));
+        let answer = post
+            .answer("On envoie ce SMS ?")
+            .nattachments(vec![confirm]);
+        self.client.post(&answer)?;
+
+        Ok(())
+    }
+
+    /// Take the pending SMS for token if it was asked by user_id.
+    fn take_pending(&self, token: &str, user_id: &str) -> Option<Pending> {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get(token) {
+            Some(p) if p.user_id == user_id && !p.expired() => pending.remove(token),
+            _ => None,
         }
     }
 }
 
-impl<S: SMSSender, D: db::SMS, C: client::Sender> Handler for SMS<S, D, C> {
+impl<S: SMSSender, D: db::SMS + db::Audit, C: client::Sender> Handler for SMS<S, D, C> {
     type Data = Post;
 
     fn name(&self) -> String {
@@ -123,7 +247,8 @@ impl<S: SMSSender, D: db::SMS, C: client::Sender> Handler for SMS<S, D, C> {
     }
 
     fn help(&self) -> Option<String> {
-        Some("Envoyer des sms.
+        let mut help = String::from(
+            "Envoyer des sms.
 
  * **Pour un usage raisonné et responsable.** Oui c'est cliché mais le contenu des messages devient ma responsabilité :)
  * Le service a un coût, actuellement 21€ TTC pour 300 SMS à utiliser sur 1 an.
@@ -134,6 +259,7 @@ Voici comment procéder pour utiliser la fonctionnalité :
 
 ```
 # Enregistrer un numéro associé à un nom/pseudo/ce que vous voulez.
+# Uniquement depuis un canal privé, pour ne pas exposer le numéro.
 !sms register <contact:[a-zA-Z0-9]+> <numéro>
 
 # Préparer un envoi que vous pensez récurrent.
@@ -147,7 +273,13 @@ Voici comment procéder pour utiliser la fonctionnalité :
 !sms list
 ```
 
-Pour chaque commande réussie, le bot ajoutera l'emoji :ok_hand: sur le message de commande.
+",
+        );
+        if self.confirm {
+            help.push_str("Chaque envoi doit être confirmé avec le bouton *Envoyer* ou `!sms confirm <code>`, dans les 10 minutes.\n\n");
+        }
+        help.push_str(
+            "Pour chaque commande réussie, le bot ajoutera l'emoji :ok_hand: sur le message de commande.
 
 Exemple :
 
@@ -165,7 +297,27 @@ Exemple :
  * L'envoi n'est pas garanti si le message contient des caractères exotiques, ça devrait quand même passer m'enfin attention.
  * Le service est payant, merci de ne pas en abuser :) On peut toujours s'arranger mais prévenez-moi avant :D
 
-".to_string())
+",
+        );
+        Some(help)
+    }
+
+    fn save(&self) -> Option<serde_json::Value> {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, p| !p.expired());
+        if pending.is_empty() {
+            return None;
+        }
+        serde_json::to_value(&*pending)
+            .map_err(|e| warn!("cannot save pending sms: {:?}", e))
+            .ok()
+    }
+
+    fn restore(&self, saved: serde_json::Value) -> Result {
+        let saved: HashMap<String, Pending> =
+            serde_json::from_value(saved).map_err(|e| Error::Other(e.to_string()))?;
+        self.pending.lock().unwrap().extend(saved);
+        Ok(())
     }
 
     fn handle(&self, post: &Post) -> Result {
@@ -188,7 +340,23 @@ Exemple :
                     p.0.id, p.1.name, p.0.trigname, p.0.name, p.0.text
                 ));
             }
-            self.client.reply(post, &msg)?;
+            self.client.ephemeral(post, &msg)?;
+        } else if let Some(m) = self.re_confirm.captures(msg) {
+            match self.take_pending(m.get(1).unwrap().as_str(), &post.user_id) {
+                Some(p) => self.send(post, &p.contact, &p.number, &p.name, &p.text)?,
+                None => {
+                    self.client
+                        .reply(post, "Pas d'envoi en attente, trop tard ?")?;
+                    return Ok(());
+                }
+            }
+        } else if let Some(m) = self.re_cancel.captures(msg) {
+            if self
+                .take_pending(m.get(1).unwrap().as_str(), &post.user_id)
+                .is_none()
+            {
+                return Ok(());
+            }
         } else if let Some(m) = self.re_send.captures(msg) {
             let trigname = m.get(1).unwrap().as_str();
             let prepare = self.db.get_prepare(tid, trigname)?;
@@ -197,14 +365,22 @@ Exemple :
                 let contact =
                     self.db.get_contact(tid, None, Some(&prepare.contact_id))?;
                 if let Some(contact) = contact {
-                    if let Err(e) = self.provider.send(
-                        &prepare.text,
+                    if self.confirm {
+                        return self.ask_confirm(
+                            post,
+                            &contact.name,
+                            &contact.number,
+                            &prepare.name,
+                            &prepare.text,
+                        );
+                    }
+                    self.send(
+                        post,
+                        &contact.name,
                         &contact.number,
                         &prepare.name,
-                    ) {
-                        self.client.reaction(post, "no_entry_sign")?;
-                        return Err(e.into());
-                    }
+                        &prepare.text,
+                    )?;
                 }
             } else {
                 self.client.reaction(post, "question")?;
@@ -215,18 +391,35 @@ Exemple :
             let text = m.get(3).unwrap().as_str();
 
             if let Some(contact) = self.db.get_contact(tid, Some(contact_name), None)? {
-                if let Err(e) = self.provider.send(text, &contact.number, name) {
-                    self.client.reaction(post, "no_entry_sign")?;
-                    return Err(e.into());
+                if self.confirm {
+                    return self.ask_confirm(
+                        post,
+                        &contact.name,
+                        &contact.number,
+                        name,
+                        text,
+                    );
                 }
+                self.send(post, &contact.name, &contact.number, name, text)?;
             } else {
                 let msg = format!("Pô trouvé {}", contact_name);
                 self.client.reply(post, &msg)?;
             }
         } else if let Some(m) = self.re_register.captures(msg) {
+            // channels of unknown kind may be public, direct and group
+            // channels belong to no team to store the number in.
+            if post.channel_kind != ChannelKind::Private {
+                self.client.reply(
+                    post,
+                    "Pas de numéro de téléphone dans un canal public ! Enregistre-le depuis un canal privé de l'équipe.",
+                )?;
+                return Ok(());
+            }
             let name = m.get(1).unwrap().as_str();
             let number = m.get(2).unwrap().as_str();
-            self.db.set_contact(tid, name, number)?;
+            let res = self.db.set_contact(tid, name, number);
+            audit::record(&*self.db, post, "sms", &res);
+            res?;
         } else if let Some(m) = self.re_prepare.captures(msg) {
             let trigname = m.get(1).unwrap().as_str();
             let contact_name = m.get(2).unwrap().as_str();
@@ -234,8 +427,9 @@ Exemple :
             let text = m.get(4).unwrap().as_str();
 
             if let Some(contact) = self.db.get_contact(tid, Some(contact_name), None)? {
-                self.db
-                    .set_prepare(tid, &contact.id, trigname, name, text)?;
+                let res = self.db.set_prepare(tid, &contact.id, trigname, name, text);
+                audit::record(&*self.db, post, "sms", &res);
+                res?;
             } else {
                 let msg = format!("Pô trouvé {}", contact_name);
                 self.client.reply(post, &msg)?;
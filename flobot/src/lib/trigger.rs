@@ -1,7 +1,8 @@
+use crate::audit;
 use crate::db;
 use crate::db::models::Trigger as MTrigger;
 use flobot_lib::client;
-use flobot_lib::handler::{Handler, Result};
+use flobot_lib::handler::{Error, Handler, Result};
 use flobot_lib::models::Post;
 use flobot_lib::tempo::Tempo;
 use regex::escape as escape_re;
@@ -36,6 +37,41 @@ pub fn valid_match(find: &str, message: &str) -> bool {
     true
 }
 
+/// Edit distance between a and b, counted in chars.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Names looking like name, closest first: typos and names containing it.
+pub fn similar(name: &str, names: &[String]) -> Vec<String> {
+    let max = (name.chars().count() / 3).max(1);
+    let mut found: Vec<(usize, &String)> = names
+        .iter()
+        .filter_map(|n| {
+            let d = distance(name, n);
+            if d <= max {
+                Some((d, n))
+            } else if n.contains(name) {
+                Some((d + max, n))
+            } else {
+                None
+            }
+        })
+        .collect();
+    found.sort();
+    found.into_iter().take(5).map(|(_, n)| n.clone()).collect()
+}
+
 pub struct Trigger<C, E> {
     db: Arc<E>,
     client: C,
@@ -90,14 +126,14 @@ impl<C: client::Sender, E> Trigger<C, E> {
             }
 
             if count == 20 {
-                self.client.post(&from.nmessage(&l))?;
+                self.client.ephemeral(from, &l)?;
                 count = 0;
                 l = String::new();
             }
         }
 
         if count > 0 {
-            self.client.post(&from.nmessage(&l))?;
+            self.client.ephemeral(from, &l)?;
         }
 
         Ok(())
@@ -106,8 +142,8 @@ impl<C: client::Sender, E> Trigger<C, E> {
 
 impl<C, E> Handler for Trigger<C, E>
 where
-    C: client::Sender,
-    E: db::Trigger,
+    C: client::Sender + client::Getter,
+    E: db::Trigger + db::Audit,
 {
     type Data = Post;
 
@@ -126,6 +162,7 @@ A per [channel, trigger] antispam is effective and currently configured at {} se
 
 !trigger list
 !trigger text \"trigger\" \"me\"
+!trigger text \"trigger\" \"https://picture.url\" # answer with the picture
 !trigger reaction \"trigger\" :emoji:
 !trigger del \"trigger\"
 ```",
@@ -133,6 +170,21 @@ A per [channel, trigger] antispam is effective and currently configured at {} se
         ))
     }
 
+    fn state(&self) -> Option<String> {
+        Some(format!("{} rate limits running", self.tempo.count()))
+    }
+
+    fn save(&self) -> Option<serde_json::Value> {
+        Some(serde_json::Value::String(self.tempo.dump()))
+    }
+
+    fn restore(&self, saved: serde_json::Value) -> Result {
+        let dump = saved.as_str().unwrap_or_default();
+        self.tempo
+            .restore(dump)
+            .map_err(|e| Error::Other(format!("rate limits: {}", e)))
+    }
+
     fn handle(&self, post: &Post) -> Result {
         let message = &post.message;
 
@@ -165,9 +217,9 @@ A per [channel, trigger] antispam is effective and currently configured at {} se
                 }
                 self.tempo.set(tempo_key.clone(), self.delay_repeat);
 
-                if t.text_.is_some() {
+                if let Some(text) = t.text_.as_ref() {
                     // text is sorted after emoji, so we can break here: emoji were already processed.
-                    self.client.reply(post, t.text_.as_ref().unwrap())?;
+                    client::post_or_picture(&self.client, &post.answer(text))?;
                     break;
                 } else {
                     // send all emoji reactions
@@ -191,11 +243,13 @@ A per [channel, trigger] antispam is effective and currently configured at {} se
                     return Ok(self.client.reply(post, &e.to_string())?);
                 }
 
-                let _ = self.db.add_text(
+                let res = self.db.add_text(
                     &post.team_id,
                     trigger,
                     captures.get(2).unwrap().as_str(),
                 );
+                audit::record(&*self.db, post, "trigger", &res);
+                res?;
                 return Ok(self.client.reaction(post, "ok_hand")?);
             }
             None => {}
@@ -210,11 +264,28 @@ A per [channel, trigger] antispam is effective and currently configured at {} se
                     return Ok(self.client.reply(post, &e.to_string())?);
                 }
 
-                let _ = self.db.add_emoji(
-                    &post.team_id,
-                    trigger,
-                    captures.get(2).unwrap().as_str(),
-                );
+                // unknown emoji would fail on every match.
+                let emoji = captures.get(2).unwrap().as_str();
+                if !self.client.emoji_exists(emoji)? {
+                    let names = similar(emoji, &self.client.emoji_names()?);
+                    let msg = match names.is_empty() {
+                        true => format!("emoji inconnu : `{}`", emoji),
+                        false => format!(
+                            "emoji inconnu : `{}`, essaye plutôt : {}",
+                            emoji,
+                            names
+                                .iter()
+                                .map(|n| format!(":{}: `{}`", n, n))
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        ),
+                    };
+                    return Ok(self.client.reply(post, &msg)?);
+                }
+
+                let res = self.db.add_emoji(&post.team_id, trigger, emoji);
+                audit::record(&*self.db, post, "trigger", &res);
+                res?;
                 return Ok(self.client.reaction(post, "ok_hand")?);
             }
             None => {}
@@ -222,9 +293,11 @@ A per [channel, trigger] antispam is effective and currently configured at {} se
 
         match self.match_del.captures(message) {
             Some(captures) => {
-                let _ = self
+                let res = self
                     .db
-                    .del(&post.team_id, captures.get(1).unwrap().as_str())?;
+                    .del(&post.team_id, captures.get(1).unwrap().as_str());
+                audit::record(&*self.db, post, "trigger", &res);
+                res?;
                 return Ok(self.client.reaction(post, "ok_hand")?);
             }
             None => {}
@@ -253,6 +326,20 @@ mod tests {
         assert!(vm("yes trig yes"));
     }
 
+    #[test]
+    fn test_similar() {
+        let names: Vec<String> = ["ok_hand", "hand", "raised_hand", "smile", "+1"]
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(vec!["ok_hand"], similar("ok_hands", &names));
+        assert_eq!(
+            vec!["hand", "ok_hand", "raised_hand"],
+            similar("hand", &names)
+        );
+        assert!(similar("wolf", &names).is_empty());
+    }
+
     #[test]
     fn test_valid_match_nbsp() {
         assert!(!vm("no\u{A0}trig\u{A0}no"));
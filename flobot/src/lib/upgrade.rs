@@ -0,0 +1,139 @@
+//! Replace the running bot with a new binary without restarting the service: the
+//! process executes the new binary, which takes over the state saved before.
+
+use flobot_lib::{error, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Set for the new process, to the file the state was saved to.
+pub const RESTORE_ENV: &str = "FLOBOT_RESTORE";
+
+/// State of a connection.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Connection {
+    /// handler states, by handler name.
+    pub handlers: serde_json::Value,
+    /// when tasks run next.
+    pub tasks: String,
+    /// posts seen on the websocket.
+    pub resume: serde_json::Value,
+    /// posts created by the bot.
+    #[serde(default)]
+    pub answers: serde_json::Value,
+}
+
+/// Everything carried over to the new process.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Saved {
+    /// build hash of the process that saved.
+    pub from: String,
+    /// by connection name.
+    pub connections: HashMap<String, Connection>,
+}
+
+/// First word of a line written by sha256sum.
+fn first_word(s: &str) -> String {
+    s.split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Returns the sha256 of binary if it is the one in checksum, a file as written by
+/// sha256sum.
+///
+/// ```rust
+/// # use flobot::upgrade;
+/// let dir = std::env::temp_dir().join(format!("flobot-verify-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let binary = dir.join("flobot.upgrade");
+/// let checksum = dir.join("flobot.upgrade.sha256");
+/// std::fs::write(&binary, "hello").unwrap();
+///
+/// let sum = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+/// std::fs::write(&checksum, format!("{}  flobot\n", sum)).unwrap();
+/// assert_eq!(Ok(sum.to_string()), upgrade::verify(&binary, &checksum));
+///
+/// std::fs::write(&checksum, "0123").unwrap();
+/// assert!(upgrade::verify(&binary, &checksum).is_err());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn verify(binary: &Path, checksum: &Path) -> Result<String, String> {
+    let expected = fs::read_to_string(checksum)
+        .map_err(|e| format!("cannot read {}: {}", checksum.display(), e))?;
+    let expected = first_word(&expected);
+
+    let mut hasher = Sha256::new();
+    fs::File::open(binary)
+        .and_then(|mut file| io::copy(&mut file, &mut hasher))
+        .map_err(|e| format!("cannot read {}: {}", binary.display(), e))?;
+    let actual = format!("{:x}", hasher.finalize());
+
+    if expected.is_empty() || actual != expected {
+        return Err(format!(
+            "checksum of {} is {}, expected {}",
+            binary.display(),
+            actual,
+            expected
+        ));
+    }
+    Ok(actual)
+}
+
+/// Move binary over the running one, returning the path to execute. The running
+/// process is not affected: nothing is stopped yet if this fails.
+pub fn install(binary: &Path) -> io::Result<PathBuf> {
+    // once replaced, the running binary no longer has a path.
+    let current = env::current_exe()?;
+    fs::set_permissions(binary, fs::Permissions::from_mode(0o755))?;
+    fs::rename(binary, &current)?;
+    Ok(current)
+}
+
+/// Save state and execute current, installed before, with the same arguments.
+/// Without a saved state, the new process starts afresh. Only returns on failure.
+pub fn exec(current: &Path, saved: &Saved, state: &Path) -> io::Error {
+    let mut command = Command::new(current);
+    command.args(env::args_os().skip(1));
+    let written = serde_json::to_vec(saved)
+        .map_err(io::Error::from)
+        .and_then(|content| fs::write(state, content));
+    match written {
+        Ok(()) => {
+            command.env(RESTORE_ENV, state);
+        }
+        Err(e) => error!("cannot save upgrade state {:?}: {:?}", state, e),
+    }
+    command.exec()
+}
+
+/// State saved by the previous process, when started by exec. It is restored once:
+/// the state file is removed.
+pub fn restore() -> Option<Saved> {
+    let path = env::var_os(RESTORE_ENV)?;
+    env::remove_var(RESTORE_ENV);
+
+    let saved = fs::read(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| {
+            serde_json::from_slice(&content).map_err(|e| e.to_string())
+        });
+    if let Err(e) = fs::remove_file(&path) {
+        warn!("cannot remove upgrade state {:?}: {:?}", path, e);
+    }
+    match saved {
+        Ok(saved) => Some(saved),
+        Err(e) => {
+            error!("cannot restore upgrade state {:?}: {}", path, e);
+            None
+        }
+    }
+}
@@ -1,35 +1,73 @@
+use crate::audit;
+use crate::db;
 use crate::werewolf_game as ww;
 use flobot_lib::client;
-use flobot_lib::handler::{Handler as BotHandler, Result};
-use flobot_lib::models::Post;
+use flobot_lib::handler::{Error, Handler as BotHandler, Result};
+use flobot_lib::models::{Attachment, Post};
+use flobot_lib::{debug, warn};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::convert::From;
+use std::sync::Arc;
 
-pub struct Handler<C> {
+pub struct Handler<C, A> {
     client: C,
+    /// records games started and stopped.
+    audit: Arc<A>,
     game: RefCell<ww::Game>,
     room_all: RefCell<String>,
     room_ww: RefCell<String>,
     team_id: RefCell<String>,
     game_owner: RefCell<Option<String>>,
+    /// current vote prompt, deleted once the vote is over.
+    vote_prompt: RefCell<Option<String>>,
 }
 
-impl<C> Handler<C> {
-    pub fn new(client: C) -> Self {
+impl<C, A> Handler<C, A> {
+    pub fn new(client: C, audit: Arc<A>) -> Self {
         Handler {
             client: client,
+            audit,
             room_ww: RefCell::new(String::from("")),
             room_all: RefCell::new(String::from("")),
             team_id: RefCell::new(String::from("")),
             game_owner: RefCell::new(None),
+            vote_prompt: RefCell::new(None),
             game: RefCell::new(ww::Game::new()),
         }
     }
 }
 
+/// A game and its channels, carried over to the next process on upgrade.
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    game: ww::Game,
+    room_all: String,
+    room_ww: String,
+    team_id: String,
+    game_owner: Option<String>,
+    vote_prompt: Option<String>,
+}
+
+/// One vote button per player.
+fn vote_buttons(players: &[ww::Player]) -> Attachment {
+    players.iter().fold(Attachment::new(""), |a, p| {
+        a.button(&p.name, &format!("!ww vote {}", p.name))
+    })
+}
+
+fn role_message(role: &ww::Role) -> &'static str {
+    match role {
+        ww::Role::Werewolf => "Tu es **loup-garou** ! Chaque nuit, choisis ta proie avec la meute sur le canal `WW-LOUPS`, et ne te fais pas démasquer le jour.",
+        ww::Role::Villager => "Tu es **villageois**. Démasque les loups-garous et vote contre eux sur le canal `WW-VILLAGE`.",
+        ww::Role::Oracle => "Tu es **voyante**. Aide le village à démasquer les loups-garous, sans trop te dévoiler.",
+    }
+}
+
 const HELP: &'static str = "Le jeu se déroule en tour par tour.
 
+ * Chacun reçoit son rôle en message privé.
  * La partie commence à la nuit tombante.
  * Les loups garous sortent et bouffent quelqu'un.
  * Le lendemain, le village trouve un cadavre.
@@ -43,12 +81,13 @@ Détails techniques :
  * Quand la nuit tombe sur le village, seuls les loups garous peuvent parler.
  * Il n'est pas interdit de discuter en MP :D
  * Il est possible d'arrêter le jeu à n'importe quel moment avec `!ww stop_game_now`
- * Les votes utilisent toujours les *username* et se font comme suit : `!ww vote <username>`
+ * Les votes utilisent toujours les *username* et se font comme suit : `!ww vote <username>`, ou en cliquant sur le bouton du joueur.
 ";
 
-impl<C> Handler<C>
+impl<C, A> Handler<C, A>
 where
-    C: client::Sender + client::Channel + client::Getter,
+    C: client::Sender + client::Channel + client::Getter + client::Presence,
+    A: db::Audit,
 {
     fn post_all(&self, post: &Post) -> Result {
         let post = post.nchannel(&self.room_all.borrow());
@@ -62,6 +101,49 @@ where
         Ok(())
     }
 
+    /// Post a vote prompt on channel_id, replacing the previous one.
+    fn post_vote(&self, post: &Post, channel_id: &str) -> Result {
+        self.clear_vote_prompt();
+        let created = self.client.create_post(&post.nchannel(channel_id))?;
+        *self.vote_prompt.borrow_mut() = Some(created.id);
+        Ok(())
+    }
+
+    fn clear_vote_prompt(&self) {
+        let prompt = self.vote_prompt.borrow_mut().take();
+        if let Some(id) = prompt {
+            if let Err(e) = self.client.delete(&id) {
+                warn!("cannot delete vote prompt: {:?}", e);
+            }
+        }
+    }
+
+    /// Privately tell each player their role.
+    fn tell_roles(&self) {
+        for player in self.game.borrow().alive_players().iter() {
+            let res = self.client.direct(&player.id).and_then(|channel_id| {
+                let post = Post::with_message(role_message(&player.role));
+                self.client.post(&post.nchannel(&channel_id))
+            });
+            if let Err(e) = res {
+                warn!("cannot tell {} their role: {:?}", player.name, e);
+            }
+        }
+    }
+
+    /// Show a game in progress in the bot status, or clear it.
+    fn show_playing(&self, playing: bool) {
+        let res = match playing {
+            true => self
+                .client
+                .custom_status("wolf", "Partie de loup-garou en cours"),
+            false => self.client.clear_custom_status(),
+        };
+        if let Err(e) = res {
+            warn!("cannot update werewolf status: {:?}", e);
+        }
+    }
+
     fn re_match(&self, re: &str, txt: &str) -> bool {
         Regex::new(re).unwrap().is_match(txt)
     }
@@ -69,6 +151,8 @@ where
     fn reset_game(&self) {
         *self.game_owner.borrow_mut() = None;
         *self.game.borrow_mut() = ww::Game::new();
+        self.clear_vote_prompt();
+        self.show_playing(false);
     }
 
     fn handle_starting_commands(&self, post: &Post, cur: &ww::Step) -> Result {
@@ -92,9 +176,12 @@ where
                             .is_ok()
                         {
                             self.client.reaction(&post, "ok_hand")?;
-                            self.client.post(&post.nmessage(
-                                "Une partie de loup-garou va démarrer ! Pour joindre la partie : `!ww join`",
-                            ))?;
+                            self.client.post(
+                                &post
+                                    .nmessage("Une partie de loup-garou va démarrer ! Pour joindre la partie : `!ww join`")
+                                    .nattachments(vec![Attachment::new("")
+                                        .button("Rejoindre", "!ww join")]),
+                            )?;
                         }
                     }
                 }
@@ -134,6 +221,8 @@ where
                                     &post.nmessage("### La partie commence !"),
                                 )?;
                                 self.post_all(&post.nmessage(HELP))?;
+                                self.tell_roles();
+                                self.show_playing(true);
                             }
                         }
                     }
@@ -151,7 +240,13 @@ where
                     if res.is_ok() {
                         self.client.reaction(&post, "ok_hand")?;
                         if res.unwrap() {
-                            self.client.post(&post.nmessage("La partie peut démarrer. Il est toujours possible de joindre la partie. Quand vous êtes prêts, démarrez avec `!ww start`"))?;
+                            self.client.post(
+                                &post
+                                    .nmessage("La partie peut démarrer. Il est toujours possible de joindre la partie. Quand vous êtes prêts, démarrez avec `!ww start`")
+                                    .nattachments(vec![Attachment::new("")
+                                        .button("Rejoindre", "!ww join")
+                                        .button("Démarrer", "!ww start")]),
+                            )?;
                         }
                     }
                 }
@@ -182,7 +277,7 @@ where
         let re_vote = Regex::new(r"!ww[\s]+vote[\s]+([\S]+)[\s]*").unwrap();
 
         loop {
-            println!("WW GAME STEP: {:?}", self.game.borrow().current_step());
+            debug!("WW GAME STEP: {:?}", self.game.borrow().current_step());
             let step = self.game.borrow().current_step();
             match step {
                 ww::Step::None | ww::Step::Ready => {}
@@ -199,7 +294,12 @@ where
                             "### Le soleil se couche, les villageois aussi…",
                         ))?;
                         let msg = format!("### Vous avez FAIM !\nChoisissez avec `!ww vote <name>` :\n{}", names);
-                        self.post_ww(&post.nmessage(&msg))?;
+                        self.post_vote(
+                            &post
+                                .nmessage(&msg)
+                                .nattachments(vec![vote_buttons(&players)]),
+                            &self.room_ww.borrow(),
+                        )?;
                         break;
                     }
                 }
@@ -220,6 +320,7 @@ where
                     }
                 }
                 ww::Step::NewDay => {
+                    self.clear_vote_prompt();
                     let res = self.game.borrow_mut().process(ww::Action::WhoDead);
                     if let Ok(ww::ActionAnswer::WhoDead(players)) = res {
                         let names = players
@@ -244,7 +345,12 @@ where
                             "### Votez qui selon vous est un loup garou !\n{}",
                             names
                         );
-                        self.post_all(&post.nmessage(&msg))?;
+                        self.post_vote(
+                            &post
+                                .nmessage(&msg)
+                                .nattachments(vec![vote_buttons(&players)]),
+                            &self.room_all.borrow(),
+                        )?;
                         break;
                     }
                 }
@@ -288,9 +394,10 @@ where
     }
 }
 
-impl<C> BotHandler for Handler<C>
+impl<C, A> BotHandler for Handler<C, A>
 where
-    C: client::Sender + client::Channel + client::Getter,
+    C: client::Sender + client::Channel + client::Getter + client::Presence,
+    A: db::Audit,
 {
     type Data = Post;
 
@@ -310,6 +417,55 @@ where
         )
     }
 
+    fn state(&self) -> Option<String> {
+        let game = self.game.borrow();
+        let step = game.current_step();
+        if step == ww::Step::None {
+            return None;
+        }
+        let players: Vec<String> = game
+            .all_players()
+            .iter()
+            .map(|p| {
+                if p.alive {
+                    p.name.clone()
+                } else {
+                    format!("~~{}~~", p.name)
+                }
+            })
+            .collect();
+        Some(format!("step {:?}, players: {}", step, players.join(", ")))
+    }
+
+    fn save(&self) -> Option<serde_json::Value> {
+        if self.game.borrow().current_step() == ww::Step::None {
+            return None;
+        }
+        let saved = Saved {
+            game: self.game.borrow().clone(),
+            room_all: self.room_all.borrow().clone(),
+            room_ww: self.room_ww.borrow().clone(),
+            team_id: self.team_id.borrow().clone(),
+            game_owner: self.game_owner.borrow().clone(),
+            vote_prompt: self.vote_prompt.borrow().clone(),
+        };
+        serde_json::to_value(&saved)
+            .map_err(|e| warn!("cannot save werewolf game: {:?}", e))
+            .ok()
+    }
+
+    fn restore(&self, saved: serde_json::Value) -> Result {
+        let saved: Saved =
+            serde_json::from_value(saved).map_err(|e| Error::Other(e.to_string()))?;
+        *self.game.borrow_mut() = saved.game;
+        *self.room_all.borrow_mut() = saved.room_all;
+        *self.room_ww.borrow_mut() = saved.room_ww;
+        *self.team_id.borrow_mut() = saved.team_id;
+        *self.game_owner.borrow_mut() = saved.game_owner;
+        *self.vote_prompt.borrow_mut() = saved.vote_prompt;
+        Ok(())
+    }
+
     fn handle(&self, post: &Post) -> Result {
         let message = &post.message;
 
@@ -318,8 +474,14 @@ where
         }
 
         if message.starts_with("!ww stop_game_now") {
+            // stopping cannot fail, only telling it can.
             self.reset_game();
+            audit::record(&*self.audit, post, "werewolf", &Ok::<(), Error>(()));
             self.client.reply(post, "Jeu arrêté.")?;
+        } else if self.re_match(r"!ww[\s]+start.*", message) {
+            let res = self.handle_game(post);
+            audit::record(&*self.audit, post, "werewolf", &res);
+            res?;
         } else {
             self.handle_game(post)?;
         }
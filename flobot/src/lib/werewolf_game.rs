@@ -1,13 +1,14 @@
 use rand::{prelude::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Role {
     Werewolf,
     Villager,
     Oracle,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Player {
     pub id: String,
     pub name: String,
@@ -47,7 +48,7 @@ pub enum ActionAnswer {
     WhoDead(Vec<Player>),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Step {
     None,
     WaitPlayers,
@@ -60,6 +61,7 @@ pub enum Step {
     End,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Game {
     players: Vec<Player>,
     step: Step,
@@ -0,0 +1,269 @@
+use flobot_lib::panic;
+use flobot_lib::{debug, error, info};
+use simple_server as ss;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long accept waits before checking if the server is stopping.
+const POLL: Duration = Duration::from_millis(100);
+/// A client sending nothing for this long is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Larger requests, headers and body, are refused.
+const MAX_REQUEST: usize = 1 << 20;
+
+pub type Route = Box<
+    dyn Fn(ss::Request<Vec<u8>>, ss::ResponseBuilder) -> ss::ResponseResult
+        + Send
+        + Sync,
+>;
+
+/// Router dispatches requests to the route registered for their exact path, or to
+/// the fallback route.
+#[derive(Default)]
+pub struct Router {
+    routes: HashMap<String, Route>,
+    fallback: Option<Route>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route(&mut self, path: &str, route: Route) {
+        self.routes.insert(path.to_string(), route);
+    }
+
+    /// Route for requests matching no path.
+    pub fn fallback(&mut self, route: Route) {
+        self.fallback = Some(route);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty() && self.fallback.is_none()
+    }
+
+    fn handle(
+        &self,
+        request: ss::Request<Vec<u8>>,
+        mut response: ss::ResponseBuilder,
+    ) -> ss::ResponseResult {
+        let route = self
+            .routes
+            .get(request.uri().path())
+            .or(self.fallback.as_ref());
+
+        match route {
+            Some(route) => route(request, response),
+            None => Ok(response.status(404).body(b"not found".to_vec())?),
+        }
+    }
+}
+
+/// A running web server.
+pub struct Server {
+    stopping: Arc<AtomicBool>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl Server {
+    /// Stop accepting requests, and wait for the request being served.
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            if thread.join().is_err() {
+                error!("webserver thread panicked");
+            }
+        }
+    }
+}
+
+fn invalid(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Read a request, its body being as long as its Content-Length.
+///
+/// ```rust
+/// # use flobot::www;
+/// let raw = b"POST /actions/a HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}";
+/// let request = www::read_request(&mut &raw[..]).unwrap();
+/// assert_eq!("/actions/a", request.uri().path());
+/// assert_eq!(b"{}", &request.body()[..]);
+///
+/// assert!(www::read_request(&mut &b"GET / HTTP/1.1\r\n"[..]).is_err());
+/// ```
+pub fn read_request(stream: &mut impl Read) -> io::Result<ss::Request<Vec<u8>>> {
+    let mut buffer = vec![];
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+        if buffer.len() > MAX_REQUEST {
+            return Err(invalid("request too large"));
+        }
+
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut parsed = httparse::Request::new(&mut headers);
+        let start = match parsed.parse(&buffer).map_err(invalid)? {
+            httparse::Status::Partial => continue,
+            httparse::Status::Complete(start) => start,
+        };
+
+        let mut request = ss::Request::builder();
+        request
+            .method(parsed.method.unwrap_or_default())
+            .uri(parsed.path.unwrap_or_default());
+        let mut length = 0;
+        for header in parsed.headers.iter() {
+            if header.name.eq_ignore_ascii_case("content-length") {
+                length = std::str::from_utf8(header.value)
+                    .ok()
+                    .and_then(|v| v.trim().parse().ok())
+                    .ok_or_else(|| invalid("invalid content-length"))?;
+            }
+            request.header(header.name, header.value);
+        }
+        if start + length > MAX_REQUEST {
+            return Err(invalid("request too large"));
+        }
+
+        let mut body = buffer[start..].to_vec();
+        body.truncate(length);
+        if body.len() < length {
+            let mut rest = vec![0; length - body.len()];
+            stream.read_exact(&mut rest)?;
+            body.extend(rest);
+        }
+        return request.body(body).map_err(invalid);
+    }
+}
+
+fn write_response(
+    response: ss::Response<Vec<u8>>,
+    stream: &mut impl Write,
+) -> io::Result<()> {
+    let status = response.status();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_str(),
+        status.canonical_reason().unwrap_or_default()
+    )
+    .into_bytes();
+    let headers = response.headers();
+    if !headers.contains_key("connection") {
+        head.extend_from_slice(b"connection: close\r\n");
+    }
+    if !headers.contains_key("content-length") {
+        head.extend_from_slice(
+            format!("content-length: {}\r\n", response.body().len()).as_bytes(),
+        );
+    }
+    for (name, value) in headers.iter() {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+
+    stream.write_all(&head)?;
+    stream.write_all(response.body())?;
+    stream.flush()
+}
+
+/// Serve a request of stream. Routes failing get a 500.
+fn handle_connection(router: &Router, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request = read_request(&mut stream)?;
+    let response = match router.handle(request, ss::Response::builder()) {
+        Ok(response) => response,
+        Err(e) => {
+            error!("webserver route failed: {:?}", e);
+            ss::Response::builder()
+                .status(500)
+                .body(b"internal server error".to_vec())
+                .map_err(invalid)?
+        }
+    };
+    write_response(response, &mut stream)
+}
+
+/// Serve router on listen, an address like `localhost:6799`, from a background
+/// thread, until stopped. Requests are served one at a time.
+pub fn serve(router: Router, listen: &str) -> io::Result<Server> {
+    let listener = TcpListener::bind(listen)?;
+    // accept must return now and then to see the server is stopping.
+    listener.set_nonblocking(true)?;
+    let stopping = Arc::new(AtomicBool::new(false));
+    info!("launch webserver on {}", listen);
+
+    let thread = {
+        let stopping = stopping.clone();
+        thread::Builder::new()
+            .name("webserver".to_string())
+            .spawn(move || {
+                while !stopping.load(Ordering::SeqCst) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            match panic::catch(|| handle_connection(&router, stream)) {
+                                Ok(Ok(())) => {}
+                                Ok(Err(e)) => debug!("webserver connection: {:?}", e),
+                                Err(p) => {
+                                    error!("webserver route panicked: {}", p.message)
+                                }
+                            }
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(POLL)
+                        }
+                        Err(e) => {
+                            error!("webserver cannot accept: {:?}", e);
+                            thread::sleep(POLL);
+                        }
+                    }
+                }
+                info!("webserver stopped");
+            })?
+    };
+
+    Ok(Server {
+        stopping,
+        thread: Mutex::new(Some(thread)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_returns() {
+        let server = serve(Router::new(), "127.0.0.1:0").unwrap();
+        server.stop();
+        server.stop();
+    }
+
+    #[test]
+    fn response_has_length() {
+        let response = ss::Response::builder()
+            .status(404)
+            .body(b"not found".to_vec())
+            .unwrap();
+        let mut out = vec![];
+        write_response(response, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(out.contains("content-length: 9\r\n"));
+        assert!(out.ends_with("\r\n\r\nnot found"));
+    }
+}
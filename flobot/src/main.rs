@@ -1,43 +1,73 @@
 #[macro_use]
 extern crate diesel_migrations;
+use chrono::Local;
 use dotenv;
 use flobot::db;
 use flobot::joke;
 use flobot::weather::Meteo;
 use flobot::{
-    edits::Edit as HandlerEdit, pinterest::Pinterest, sms,
-    trigger::Trigger as HandlerTrigger, werewolf::Handler as HandlerWW,
+    audit, bridge, cleanup::Cleanup, edits::Edit as HandlerEdit, lifecycle::Supervisor,
+    pinterest::Pinterest, sms, systemd, trigger::Trigger as HandlerTrigger, upgrade,
+    werewolf::Handler as HandlerWW, www,
 };
-use flobot_lib::client::Getter;
+use flobot_lib::client::{self, Notifier, Presence};
 use flobot_lib::conf::Conf;
 use flobot_lib::handler::MutexedHandler;
+use flobot_lib::health::Health;
 use flobot_lib::instance::Instance;
+use flobot_lib::memory::Memory;
+use flobot_lib::metrics;
 use flobot_lib::middleware;
 use flobot_lib::models::Event;
 use flobot_lib::task::*;
 use flobot_lib::tempo::Tempo;
+use flobot_lib::{debug, error, info, log, warn};
 use flobot_mattermost::client::Mattermost;
+use flobot_mattermost::record;
 use signal_libc::signal::{self, Signal};
 use simple_server as ss;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::path::PathBuf;
 use std::sync::mpsc::channel;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 embed_migrations!();
 
-fn make_jokes_provider(botdb: Arc<db::sqlite::Sqlite>) -> joke::SelectProvider {
+type BotDB = db::namespace::Namespaced<db::sqlite::Sqlite>;
+type PinterestMM = Pinterest<Mattermost>;
+
+/// Without progress for that long, the bot is considered hung. Handlers and tasks
+/// may take a while, the websocket is pinged every 30 seconds and reconnects at
+/// least every 5 minutes.
+const INSTANCE_HUNG: Duration = Duration::from_secs(5 * 60);
+const TASKS_HUNG: Duration = Duration::from_secs(15 * 60);
+const WEBSOCKET_HUNG: Duration = Duration::from_secs(15 * 60);
+
+/// How long threads get to finish their work once the bot is stopping, unless
+/// BOT_SHUTDOWN_TIMEOUT says otherwise, in seconds.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Jokes providers configured in cfg, without the remote APIs when offline.
+fn make_jokes_provider(
+    cfg: &Conf,
+    botdb: Arc<BotDB>,
+    offline: bool,
+) -> joke::SelectProvider {
     let mut joke_remotes = joke::SelectProvider::new(vec![]);
-    joke_remotes.push(Arc::new(joke::ProviderBadJokes::new()));
     joke_remotes.push(Arc::new(joke::ProviderSQLite::new(botdb)));
-    if let Ok(token) = env::var("BOT_BLAGUESAPI_TOKEN") {
-        let blaguesapi = joke::ProviderBlaguesAPI::new(&token);
-        joke_remotes.push(Arc::new(blaguesapi));
+    if !offline {
+        joke_remotes.push(Arc::new(joke::ProviderBadJokes::new()));
+        if let Ok(token) = cfg.var("BLAGUESAPI_TOKEN") {
+            let blaguesapi = joke::ProviderBlaguesAPI::new(&token);
+            joke_remotes.push(Arc::new(blaguesapi));
+        }
     }
 
-    if let Ok(filepath) = env::var("BOT_BLAGUES_URLS") {
+    if let Ok(filepath) = cfg.var("BLAGUES_URLS") {
         if let Ok(content) = fs::read_to_string(filepath.clone()) {
             let mut urls = vec![];
             for line in content.split("\n") {
@@ -46,40 +76,71 @@ fn make_jokes_provider(botdb: Arc<db::sqlite::Sqlite>) -> joke::SelectProvider {
 
             joke_remotes.push(Arc::new(joke::ProviderFile { urls }));
         } else {
-            println!("cannot read jokes from {}", filepath);
+            warn!("cannot read jokes from {}", filepath);
         }
     }
 
     joke_remotes
 }
 
-fn bot() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    println!("Launch version {}", flobot_lib::BUILD_GIT_HASH);
-    let cli_args: Vec<String> = env::args().collect();
-    let mut flag_debug = false;
-    println!("Launched with command line arguments: {:?}", cli_args);
-    for cli_arg in cli_args {
-        if cli_arg.eq("--debug") {
-            flag_debug = true;
-        }
-    }
-
-    dotenv::from_filename("flobot.env").ok();
-    let cfg = Conf::new().expect("cfg err");
-    let mm = Mattermost::new(cfg.clone())?;
+/// Everything the bot runs against a single chat server: its client, the instance
+/// dispatching events to handlers and the tasks.
+struct Connection<C> {
+    name: String,
+    client: C,
+    instance: Instance<C>,
+    taskrunner: SequentialTaskRunner,
+}
 
-    let db_url: &str = &cfg.db_url;
+/// Everything handlers need from a chat client.
+trait BotClient:
+    client::Sender
+    + client::Editor
+    + client::Channel
+    + client::Getter
+    + client::Notifier
+    + client::Presence
+    + client::Recall
+    + Clone
+    + Send
+    + Sync
+    + 'static
+{
+}
 
-    println!("run db migrations");
-    let conn = db::conn(db_url);
-    embedded_migrations::run(&conn)?;
+impl<T> BotClient for T where
+    T: client::Sender
+        + client::Editor
+        + client::Channel
+        + client::Getter
+        + client::Notifier
+        + client::Presence
+        + client::Recall
+        + Clone
+        + Send
+        + Sync
+        + 'static
+{
+}
 
-    println!("init");
+fn connect<C: BotClient>(
+    cfg: &Conf,
+    client: C,
+    sqlite: Arc<db::sqlite::Sqlite>,
+    pinterest: Option<Arc<PinterestMM>>,
+    network: Arc<bridge::Network<C>>,
+    flag_debug: bool,
+    offline: bool,
+) -> std::result::Result<Connection<C>, Box<dyn std::error::Error>> {
+    info!("init connection {}", cfg.name);
 
     // BASICS
-    let mm_client = Mattermost::new(cfg.clone())?;
-    let mut instance = Instance::new(mm_client.clone());
-    let botdb = Arc::new(db::sqlite::new(conn));
+    let mut instance = Instance::new(client.clone());
+    instance.set_admins(cfg.var_list("ADMINS"));
+    let botdb = Arc::new(db::namespace::Namespaced::new(
+        sqlite.clone(),
+        &cfg.namespace,
+    ));
 
     // TASKRUNNER
     let mut taskrunner = SequentialTaskRunner::new();
@@ -87,195 +148,637 @@ fn bot() -> std::result::Result<(), Box<dyn std::error::Error>> {
 
     // MIDDLEWARE
     let ignore_self =
-        middleware::IgnoreSelf::new(mm_client.my_user_id().to_string().clone());
+        middleware::IgnoreSelf::new(client.my_user_id().to_string().clone());
     if flag_debug {
         instance.add_middleware(Box::new(middleware::Debug::new("debug")));
     }
     instance.add_middleware(Box::new(ignore_self));
 
     // TRIGGER
-    let trigger_delay_secs = Duration::from_secs(
-        std::env::var("BOT_TRIGGER_DELAY_SECONDS")
-            .unwrap_or("0".to_string())
-            .parse()
-            .unwrap(),
-    );
-    println!(
-        "trigger configured with delay of {} seconds",
-        trigger_delay_secs.as_secs()
-    );
-    let trigger = HandlerTrigger::new(
-        botdb.clone(),
-        mm_client.clone(),
-        Tempo::new(),
-        trigger_delay_secs,
-    );
-    instance.add_post_handler(Box::new(trigger));
+    if cfg.handler_enabled("trigger") {
+        let trigger_delay_secs = Duration::from_secs(
+            cfg.var("TRIGGER_DELAY_SECONDS")
+                .unwrap_or("0".to_string())
+                .parse()
+                .unwrap(),
+        );
+        info!(
+            "trigger configured with delay of {} seconds",
+            trigger_delay_secs.as_secs()
+        );
+        let trigger = HandlerTrigger::new(
+            botdb.clone(),
+            client.clone(),
+            Tempo::new(),
+            trigger_delay_secs,
+        );
+        instance.add_post_handler(Box::new(trigger));
+    }
 
     // EDIT
-    let edits = HandlerEdit::new(botdb.clone(), mm_client.clone());
-    instance.add_post_handler(Box::new(edits));
+    if cfg.handler_enabled("edits") {
+        let edits = HandlerEdit::new(botdb.clone(), client.clone());
+        instance.add_post_handler(Box::new(edits));
+    }
 
     // JOKES
-    let mut jokeprovider = make_jokes_provider(botdb.clone());
-
-    // PINTEREST
-    let mut handler: Option<_> = None;
-    if let (Ok(client_id), Ok(client_secret), Ok(board_id), Ok(redirect)) = (
-        env::var("PINTEREST_CLIENT_ID"),
-        env::var("PINTEREST_CLIENT_SECRET"),
-        env::var("PINTEREST_BOARD_ID"),
-        env::var("PINTEREST_REDIRECT"),
-    ) {
-        println!("loading pinterest");
-        let pinterest = Arc::new(Pinterest::new(
-            &client_id,
-            &client_secret,
-            &redirect,
-            &board_id,
-            mm_client.clone(),
-        ));
-
-        jokeprovider.push(pinterest.clone());
-        taskrunner.add(pinterest.clone());
-
-        handler = Some(
-            move |request: ss::Request<Vec<u8>>,
-                  mut response: ss::ResponseBuilder|
-                  -> ss::ResponseResult {
-                let furl = format!("http://localhost{}", request.uri());
-
-                let mut code = "".to_string();
-                let mut state = "".to_string();
-
-                if let Ok(url) = url::Url::parse(&furl) {
-                    for qp in url.query_pairs() {
-                        if qp.0 == "code" {
-                            code = qp.1.to_string();
-                        } else if qp.0 == "state" {
-                            state = qp.1.to_string();
-                        }
-                    }
-                }
-
-                println!("pinterest: got code {}", code);
-                if pinterest.authenticate(&code, &state) {
-                    println!("authenticated!");
-                    return Ok(response
-                        .status(200)
-                        .body("Authenticated!".as_bytes().to_vec())?);
-                }
-
-                println!("pinterest: failed to authenticate");
+    if cfg.handler_enabled("joke") {
+        let mut jokeprovider = make_jokes_provider(cfg, botdb.clone(), offline);
+        if let Some(pinterest) = pinterest {
+            jokeprovider.push(pinterest.clone());
+            taskrunner.add(pinterest);
+        }
 
-                Ok(response
-                    .status(500)
-                    .body("NOT AUTHENTICATED".as_bytes().to_vec())?)
-            },
-        );
+        let handler_joke =
+            joke::Handler::new(botdb.clone(), jokeprovider, client.clone());
+        instance.add_post_handler(Box::new(MutexedHandler::from(handler_joke)));
     }
 
-    let handler_joke =
-        joke::Handler::new(botdb.clone(), jokeprovider, mm_client.clone());
-    instance.add_post_handler(Box::new(MutexedHandler::from(handler_joke)));
-
     // WEREWOLF GAME
-    let ww = HandlerWW::new(mm_client.clone());
-    instance.add_post_handler(Box::new(MutexedHandler::from(ww)));
+    if cfg.handler_enabled("werewolf") {
+        let ww = HandlerWW::new(client.clone(), botdb.clone());
+        instance.add_post_handler(Box::new(MutexedHandler::from(ww)));
+    }
 
     // SMS
-    if let (Ok(login), Ok(apikey)) = (
-        env::var("BOT_OCTOPUSH_LOGIN"),
-        env::var("BOT_OCTOPUSH_APIKEY"),
+    if let (true, Ok(login), Ok(apikey)) = (
+        cfg.handler_enabled("sms"),
+        cfg.var("OCTOPUSH_LOGIN"),
+        cfg.var("OCTOPUSH_APIKEY"),
     ) {
-        let smsprov = sms::Octopush::new(&login, &apikey);
-        let sms = sms::SMS::new(smsprov, botdb.clone(), mm_client.clone());
-        instance.add_post_handler(Box::new(sms));
+        let confirm = cfg.var("SMS_CONFIRM").is_ok_and(|v| v == "true");
+        if offline {
+            let sms = sms::SMS::new(sms::Dry, botdb.clone(), client.clone(), confirm);
+            instance.add_post_handler(Box::new(sms));
+        } else {
+            let smsprov = sms::Octopush::new(&login, &apikey);
+            let sms = sms::SMS::new(smsprov, botdb.clone(), client.clone(), confirm);
+            instance.add_post_handler(Box::new(sms));
+        }
+    }
+
+    // BRIDGE
+    network.join(&cfg.name, client.clone());
+    if cfg.handler_enabled("bridge") {
+        let bridge = bridge::Bridge::new(
+            &cfg.name,
+            client.clone(),
+            sqlite,
+            network,
+            cfg.var_list("ADMINS"),
+        );
+        instance.add_event_handler(Box::new(bridge));
+    }
+
+    // CLEANUP
+    if cfg.handler_enabled("cleanup") {
+        let cleanup = Cleanup::new(client.clone(), cfg.var_list("ADMINS"));
+        instance.add_event_handler(Box::new(cleanup));
+    }
+
+    // AUDIT
+    if cfg.handler_enabled("audit") {
+        let audit =
+            audit::Handler::new(client.clone(), botdb.clone(), cfg.var_list("ADMINS"));
+        instance.add_post_handler(Box::new(audit));
     }
 
     // METEO
-    if let (Ok(cities), Ok(channel)) = (
-        env::var("BOT_METEO_CITIES"),
-        env::var("BOT_METEO_ON_CHANNEL_ID"),
-    ) {
+    if let (Ok(cities), Ok(channel)) =
+        (cfg.var("METEO_CITIES"), cfg.var("METEO_ON_CHANNEL_ID"))
+    {
         let cities = cities.split(',').map(|p| p.to_string()).collect();
-        println!(
+        info!(
             "exec meteo in {:?}",
-            taskrunner.add(Arc::new(Meteo::new(cities, mm_client.clone(), &channel)))
+            taskrunner.add(Arc::new(Meteo::new(cities, client.clone(), &channel)))
         );
     }
 
-    // RUN FOREVER
-    println!("launch bot!");
-    let (sender, receiver) = channel();
-    let _listener_t = {
-        let sender = sender.clone();
-        thread::spawn(move || {
-            println!("launch client thread");
-            mm.listen(sender);
-            println!("client thread returned");
-        })
-    };
+    Ok(Connection {
+        name: cfg.name.clone(),
+        client,
+        instance,
+        taskrunner,
+    })
+}
 
-    let taskrunner = Arc::new(taskrunner);
-    let taskrunner_t = {
-        let taskrunner = taskrunner.clone();
-        thread::spawn(move || {
-            println!("launch task runner");
-            taskrunner.run_forever();
-            println!("task runner returned");
-        })
-    };
+/// A connection started, for state dumps.
+struct Running {
+    name: String,
+    client: Mattermost,
+    instance: Arc<Instance<Mattermost>>,
+    taskrunner: Arc<SequentialTaskRunner>,
+}
 
-    let instance_t = {
-        thread::spawn(move || {
-            if let Err(e) = instance.run(receiver) {
-                println!("instance returned with error: {:?}", e);
-            }
-            println!("instance return without error");
+/// Post the state of each connection to its debug channel, and all of them to the
+/// file at path.
+fn dump(running: &[Running], path: &str) {
+    let mut all = format!(
+        "# flobot {} state at {}\n",
+        flobot_lib::BUILD_GIT_HASH,
+        Local::now().format("%F %T")
+    );
+    for r in running.iter() {
+        let state = format!(
+            "## State of {}\n### Instance\n{}### Tasks\n{}### Websocket\n{}\n",
+            r.name,
+            r.instance.state(),
+            r.taskrunner.state(),
+            r.client.state()
+        );
+        if let Err(e) = r.client.debug(&state) {
+            warn!("{}: cannot post state dump: {:?}", r.name, e);
+        }
+        all.push_str(&state);
+    }
+    match fs::write(path, all) {
+        Ok(()) => info!("state dumped to {}", path),
+        Err(e) => warn!("cannot write state dump to {}: {:?}", path, e),
+    }
+}
+
+/// State of every connection, for the next process on upgrade.
+fn save(running: &[Running]) -> upgrade::Saved {
+    let connections = running
+        .iter()
+        .map(|r| {
+            let saved = upgrade::Connection {
+                handlers: r.instance.save(),
+                tasks: r.taskrunner.save(),
+                resume: r.client.save_resume(),
+                answers: r.client.save_answers(),
+            };
+            (r.name.clone(), saved)
         })
+        .collect();
+    upgrade::Saved {
+        from: flobot_lib::BUILD_GIT_HASH.to_string(),
+        connections,
+    }
+}
+
+/// Take over the state connection had in the previous process.
+fn restore(connection: &mut Connection<Mattermost>, restored: &mut upgrade::Saved) {
+    connection
+        .instance
+        .announce(&format!("## Upgraded from\n * `{}`\n", restored.from));
+    let saved = match restored.connections.remove(&connection.name) {
+        Some(saved) => saved,
+        None => return,
     };
+    connection.instance.restore(saved.handlers);
+    if let Err(e) = connection.taskrunner.restore(&saved.tasks) {
+        warn!("{}: cannot restore tasks: {:?}", connection.name, e);
+    }
+    if let Err(e) = connection.client.restore_resume(saved.resume) {
+        warn!(
+            "{}: cannot restore websocket resume: {:?}",
+            connection.name, e
+        );
+    }
+    if let Err(e) = connection.client.restore_answers(saved.answers) {
+        warn!("{}: cannot restore answers: {:?}", connection.name, e);
+    }
+    info!("{}: state restored", connection.name);
+}
+
+/// Feed the websocket frames recorded in path to the handlers of the first
+/// connection, with an in-memory client and database, then print what the bot did.
+/// Nothing leaves the process: no sms is sent, files are not downloaded and jokes
+/// only come from the database and BLAGUES_URLS.
+fn replay(
+    path: &str,
+    flag_debug: bool,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let cfg = Conf::load_all().expect("cfg err").remove(0);
+    let recording = record::replay_file(path)?;
+    info!("replay {} events from {}", recording.events.len(), path);
+
+    let conn = db::conn(":memory:");
+    embedded_migrations::run(&conn)?;
+    let client = Memory::new(recording.me.as_deref().unwrap_or("bot"));
+    let connection = connect(
+        &cfg,
+        client.clone(),
+        Arc::new(db::sqlite::new(conn)),
+        None,
+        Arc::new(bridge::Network::new()),
+        flag_debug,
+        true,
+    )?;
 
-    if let Some(handler) = handler {
-        println!("starting webserver with declared handler");
-        let _www_t = thread::spawn(move || loop {
-            // survive crashes from webserver
-            let hh = handler.clone();
-            let r = thread::spawn(move || {
-                let mut server = ss::Server::new(hh);
-                server.dont_serve_static_files();
-                println!("launch webserver on localhost:6799");
-                server.listen("localhost", "6799");
-            })
-            .join();
-            println!("webserver thread loop returned: {:?}", r);
+    let (sender, receiver) = channel();
+    for event in recording.events {
+        sender.send(event)?;
+    }
+    sender.send(Event::Shutdown)?;
+    if let Err(e) = connection.instance.run(receiver) {
+        error!("replay stopped with error: {:?}", e);
+    }
+
+    for line in client.log() {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn bot() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    dotenv::from_filename("flobot.env").ok();
+    log::init();
+    info!("Launch version {}", flobot_lib::BUILD_GIT_HASH);
+    let cli_args: Vec<String> = env::args().collect();
+    let mut flag_debug = false;
+    let mut replay_from = None;
+    debug!("Launched with command line arguments: {:?}", cli_args);
+    for (i, cli_arg) in cli_args.iter().enumerate() {
+        if cli_arg.eq("--debug") {
+            flag_debug = true;
+        }
+        if cli_arg.eq("--replay") {
+            replay_from = cli_args.get(i + 1).cloned();
+        }
+    }
+
+    if let Some(path) = replay_from {
+        return replay(&path, flag_debug);
+    }
+
+    let cfgs = Conf::load_all().expect("cfg err");
+
+    // connections sharing the same database url share the same connection.
+    let mut dbs: HashMap<String, Arc<db::sqlite::Sqlite>> = HashMap::new();
+    for cfg in cfgs.iter() {
+        if !dbs.contains_key(&cfg.db_url) {
+            info!("run db migrations on {}", cfg.db_url);
+            let conn = db::conn(&cfg.db_url);
+            embedded_migrations::run(&conn)?;
+            dbs.insert(cfg.db_url.clone(), Arc::new(db::sqlite::new(conn)));
+        }
+    }
+
+    debug!("init");
+
+    let mut connections = vec![];
+    let mut router = www::Router::new();
+    router.route(
+        "/metrics",
+        Box::new(|_request, mut response| {
+            Ok(response
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .body(metrics::render().into_bytes())?)
+        }),
+    );
+    let network = Arc::new(bridge::Network::new());
+    for cfg in cfgs.iter() {
+        let mm_client = Mattermost::new(cfg.clone())?;
+
+        // PINTEREST: a single oauth callback is served, so only the first connection
+        // gets it.
+        let mut pinterest: Option<Arc<PinterestMM>> = None;
+        if let (true, Ok(client_id), Ok(client_secret), Ok(board_id), Ok(redirect)) = (
+            connections.is_empty(),
+            env::var("PINTEREST_CLIENT_ID"),
+            env::var("PINTEREST_CLIENT_SECRET"),
+            env::var("PINTEREST_BOARD_ID"),
+            env::var("PINTEREST_REDIRECT"),
+        ) {
+            info!("loading pinterest");
+            log::secret(&client_secret);
+            let p = Arc::new(Pinterest::new(
+                &client_id,
+                &client_secret,
+                &redirect,
+                &board_id,
+                mm_client.clone(),
+            ));
+            pinterest = Some(p.clone());
+
+            router.fallback(Box::new(
+                move |request: ss::Request<Vec<u8>>,
+                      mut response: ss::ResponseBuilder|
+                      -> ss::ResponseResult {
+                    let furl = format!("http://localhost{}", request.uri());
+
+                    let mut code = "".to_string();
+                    let mut state = "".to_string();
+
+                    if let Ok(url) = url::Url::parse(&furl) {
+                        for qp in url.query_pairs() {
+                            if qp.0 == "code" {
+                                code = qp.1.to_string();
+                            } else if qp.0 == "state" {
+                                state = qp.1.to_string();
+                            }
+                        }
+                    }
+
+                    info!("pinterest: got authorization code");
+                    if p.authenticate(&code, &state) {
+                        info!("authenticated!");
+                        return Ok(response
+                            .status(200)
+                            .body("Authenticated!".as_bytes().to_vec())?);
+                    }
+
+                    warn!("pinterest: failed to authenticate");
+
+                    Ok(response
+                        .status(500)
+                        .body("NOT AUTHENTICATED".as_bytes().to_vec())?)
+                },
+            ));
+        }
+
+        let sqlite = dbs.get(&cfg.db_url).unwrap().clone();
+        connections.push(connect(
+            cfg,
+            mm_client,
+            sqlite,
+            pinterest,
+            network.clone(),
+            flag_debug,
+            false,
+        )?);
+    }
+
+    // UPGRADE: take over the state of the process that executed this one.
+    if let Some(mut restored) = upgrade::restore() {
+        info!("upgraded from {}", restored.from);
+        for connection in connections.iter_mut() {
+            restore(connection, &mut restored);
+        }
+    }
+
+    // RUN FOREVER
+    info!("launch bot!");
+    let mut senders = vec![];
+    let mut clients = vec![];
+    let mut taskrunners = vec![];
+    let mut running = vec![];
+    let mut supervisor = Supervisor::new();
+    let mut health = Health::new();
+    for (url, sqlite) in dbs.iter() {
+        let sqlite = sqlite.clone();
+        health.ready(
+            &format!("database {}", url),
+            Box::new(move || sqlite.ping().map_err(|e| e.to_string())),
+        );
+    }
+    for connection in connections {
+        let name = connection.name;
+        let mm = connection.client.clone();
+        health
+            .live(
+                &format!("{} instance", name),
+                connection.instance.heartbeat().check(INSTANCE_HUNG),
+            )
+            .live(
+                &format!("{} tasks", name),
+                connection.taskrunner.heartbeat().check(TASKS_HUNG),
+            )
+            .live(
+                &format!("{} websocket", name),
+                mm.heartbeat().check(WEBSOCKET_HUNG),
+            )
+            .ready(
+                &format!("{} connected", name),
+                Box::new(move || {
+                    if mm.is_connected() {
+                        Ok(())
+                    } else {
+                        Err("websocket disconnected".to_string())
+                    }
+                }),
+            );
+        let (sender, receiver) = channel();
+
+        // ACTIONS: clicks on buttons and menus of posts are sent to the bot.
+        if connection.client.actions_enabled() {
+            let mm = connection.client.clone();
+            let sender = Mutex::new(sender.clone());
+            router.route(
+                &format!("/actions/{}", name),
+                Box::new(move |request, mut response| {
+                    match mm.action(request.body()) {
+                        Some(event) => {
+                            let _ = sender.lock().unwrap().send(event);
+                            Ok(response
+                                .header("Content-Type", "application/json")
+                                .body(b"{}".to_vec())?)
+                        }
+                        None => Ok(response.status(403).body(b"forbidden".to_vec())?),
+                    }
+                }),
+            );
+        }
+        if let Err(e) = connection.client.online() {
+            warn!("{}: cannot set status online: {:?}", name, e);
+        }
+        clients.push(connection.client.clone());
+        {
+            let sender = sender.clone();
+            let mm = connection.client;
+            supervisor.spawn(&format!("{} websocket", name), move || {
+                mm.listen(sender);
+                Ok(())
+            });
+        }
+        senders.push(sender);
+
+        let taskrunner = Arc::new(connection.taskrunner);
+        {
+            let taskrunner = taskrunner.clone();
+            supervisor.spawn(&format!("{} tasks", name), move || {
+                taskrunner.run_forever();
+                Ok(())
+            });
+        }
+        taskrunners.push(taskrunner.clone());
+
+        let instance = Arc::new(connection.instance);
+        {
+            let instance = instance.clone();
+            supervisor.spawn(&format!("{} instance", name), move || {
+                instance.run(receiver).map_err(|e| format!("{:?}", e))
+            });
+        }
+        running.push(Running {
+            name,
+            client: clients.last().unwrap().clone(),
+            instance,
+            taskrunner,
         });
     }
 
-    println!("wire signals");
-    signal::register(Signal::SIGINT);
-    signal::register(Signal::SIGTERM);
+    let health = Arc::new(health);
+    for &(path, ready) in [("/healthz", false), ("/readyz", true)].iter() {
+        let health = health.clone();
+        router.route(
+            path,
+            Box::new(move |_request, mut response| {
+                let report = if ready {
+                    health.ready_report()
+                } else {
+                    health.live_report()
+                };
+                Ok(response
+                    .status(if report.ok() { 200 } else { 503 })
+                    .header("Content-Type", "text/plain")
+                    .body(report.to_string().into_bytes())?)
+            }),
+        );
+    }
+
+    let mut server = None;
+    if !router.is_empty() {
+        let listen = env::var("BOT_HTTP_LISTEN")
+            .unwrap_or_else(|_| "localhost:6799".to_string());
+        server = Some(www::serve(router, &listen)?);
+    }
+
+    // SYSTEMD: started, and alive as long as health checks pass.
+    if let Err(e) = systemd::notify("READY=1") {
+        warn!("systemd notify: {:?}", e);
+    }
+    if let Some(every) = systemd::watchdog() {
+        info!("systemd watchdog every {} seconds", every.as_secs());
+        let health = health.clone();
+        thread::spawn(move || loop {
+            thread::sleep(every / 2);
+            let report = health.live_report();
+            if !report.ok() {
+                error!("bot is hung, systemd watchdog not notified:\n{}", report);
+                continue;
+            }
+            if let Err(e) = systemd::notify("WATCHDOG=1") {
+                warn!("systemd notify: {:?}", e);
+            }
+        });
+    }
 
-    let stop_instance_t = {
-        let sender = sender.clone();
+    debug!("wire signals");
+    let stop_signals = signal::subscribe(&[Signal::SIGINT, Signal::SIGTERM])?;
+    let stopper = supervisor.stopper();
+    thread::spawn(move || {
+        let reason = match stop_signals.recv() {
+            Ok(sig) => format!("{:?} received", sig),
+            Err(e) => format!("signals lost: {}", e),
+        };
+        stopper.stop(&reason);
+    });
+
+    // DUMP: state of the bot on SIGUSR1, for live debugging.
+    let running = Arc::new(running);
+    let dump_signals = signal::subscribe(&[Signal::SIGUSR1])?;
+    let dump_path =
+        env::var("BOT_DUMP_FILE").unwrap_or_else(|_| "flobot.dump".to_string());
+    {
+        let running = running.clone();
         thread::spawn(move || {
-            loop {
-                match signal::recv() {
-                    Some(Signal::OTHER(_)) => {}
-                    Some(Signal::SIGINT) | Some(Signal::SIGTERM) | None => break,
-                    _ => {}
+            for _ in dump_signals.iter() {
+                dump(&running, &dump_path);
+            }
+        });
+    }
+
+    // UPGRADE: on SIGUSR2, install the upgrade binary once its checksum is verified,
+    // then stop and execute it. Nothing is stopped when it cannot be installed.
+    let upgrade_signals = signal::subscribe(&[Signal::SIGUSR2])?;
+    let upgrade_path = PathBuf::from(
+        env::var("BOT_UPGRADE_FILE").unwrap_or_else(|_| "flobot.upgrade".to_string()),
+    );
+    let installed: Arc<Mutex<Option<PathBuf>>> = Arc::default();
+    {
+        let running = running.clone();
+        let installed = installed.clone();
+        let stopper = supervisor.stopper();
+        thread::spawn(move || {
+            let mut checksum = upgrade_path.clone().into_os_string();
+            checksum.push(".sha256");
+            for _ in upgrade_signals.iter() {
+                let res =
+                    upgrade::verify(&upgrade_path, checksum.as_ref()).and_then(|sum| {
+                        upgrade::install(&upgrade_path)
+                            .map(|current| (sum, current))
+                            .map_err(|e| format!("cannot install: {}", e))
+                    });
+                match res {
+                    Ok((sum, current)) => {
+                        *installed.lock().unwrap() = Some(current);
+                        stopper.stop(&format!("upgrade to {}", sum));
+                        return;
+                    }
+                    Err(e) => {
+                        warn!("upgrade refused: {}", e);
+                        for r in running.iter() {
+                            let message = format!("upgrade refused: {}", e);
+                            if let Err(e) = r.client.debug(&message) {
+                                warn!("{}: debug error: {:?}", r.name, e);
+                            }
+                        }
+                    }
                 }
             }
+        });
+    }
 
-            while sender.send(Event::Shutdown).is_err() {}
-            println!("graceful stop asked");
-        })
+    // STOP: no more events in, let instances process the queued ones, then wait for
+    // every thread.
+    let reason = supervisor.wait_stop();
+    info!("graceful stop asked: {}", reason);
+    let installed = installed.lock().unwrap().take();
+    let upgrading = installed.is_some();
+    // on upgrade, the new process notifies READY=1 once started: the reload ends.
+    let state = if upgrading {
+        "RELOADING=1"
+    } else {
+        "STOPPING=1"
     };
+    if let Err(e) = systemd::notify(state) {
+        warn!("systemd notify: {:?}", e);
+    }
+    for client in clients.iter() {
+        if !upgrading {
+            if let Err(e) = client.away() {
+                warn!("cannot set status away: {:?}", e);
+            }
+        }
+        client.close();
+    }
+    if let Some(server) = server {
+        server.stop();
+    }
+    for sender in senders.iter() {
+        if sender.send(Event::Shutdown).is_err() {
+            warn!("instance already stopped");
+        }
+    }
+    for taskrunner in taskrunners.iter() {
+        taskrunner.stop();
+    }
 
-    println!("graceful stop: {:?}", stop_instance_t.join());
-    taskrunner.stop();
-    println!("taskrunner thread returned: {:?}", taskrunner_t.join());
-    println!("instance thread returned: {:?}", instance_t.join());
+    let timeout = env::var("BOT_SHUTDOWN_TIMEOUT")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(SHUTDOWN_TIMEOUT);
+    if supervisor.join(timeout) {
+        info!("every thread stopped");
+    }
+    if let Some(current) = installed {
+        let state = PathBuf::from(
+            env::var("BOT_UPGRADE_STATE")
+                .unwrap_or_else(|_| "flobot.state".to_string()),
+        );
+        info!("execute {}", current.display());
+        // the new binary is installed: the service manager restarting the bot after
+        // this error runs it too.
+        let e = upgrade::exec(&current, &save(&running), &state);
+        return Err(format!("cannot upgrade: {:?}", e).into());
+    }
+    if supervisor.failed() {
+        return Err(format!("bot stopped after a failure: {}", reason).into());
+    }
 
     Ok(())
 }
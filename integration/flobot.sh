@@ -11,6 +11,7 @@ if [ -f flobot.upgrade ]; then
 	echo "Upgrade found, moving files"
 	md5sum flobot.upgrade flobot
 	mv flobot.upgrade flobot
+	rm -f flobot.upgrade.sha256
 	chmod +x flobot
 else
 	echo "No upgrade found."
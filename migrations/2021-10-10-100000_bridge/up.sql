@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE bridge (
+    id integer primary key not null,
+    from_server varchar(256) not null,
+    from_channel varchar(256) not null,
+    to_server varchar(256) not null,
+    to_channel varchar(256) not null,
+    UNIQUE(from_server, from_channel, to_server, to_channel)
+);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE audit (
+    id integer primary key not null,
+    at_unixts bigint not null,
+    team_id varchar(256) not null,
+    channel_id varchar(256) not null,
+    user_id varchar(256) not null,
+    module varchar(64) not null,
+    command text not null,
+    outcome text not null
+);
+CREATE INDEX audit_team_at ON audit(team_id, at_unixts);
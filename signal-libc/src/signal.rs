@@ -1,18 +1,24 @@
 //! Basic signal handling using the libc crate.
 //! Once a signal has been registered it cannot be de-registered.
-//! Empirically tested.
+//!
+//! Handlers are installed with `sigaction` and `SA_RESTART`: a system call
+//! interrupted by a signal is restarted instead of failing with `EINTR`. The C
+//! handler only writes the signal number to a pipe, which is async-signal-safe. A
+//! thread reads the pipe and sends each signal to every subscriber of it.
 //!
 //! See ctrl-c or signal-hook crates for cross platform libs.
 //!
 //! See example for a ready to use boilerplate.
 
 extern crate libc;
-use std::sync::{mpsc, Arc};
+use std::io;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::thread;
 
 use libc as c;
-use libc::signal as c_signal;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Signal {
     SIGALRM,
     SIGINT,
@@ -24,77 +30,223 @@ pub enum Signal {
 
 /// ```
 /// use libc;
-/// use flobot::signal;
+/// use signal_libc::signal;
 ///
 /// let sig: signal::CSig = libc::SIGUSR1;
 /// ```
 pub type CSig = i32;
-type Recv = mpsc::Receiver<CSig>;
-type Send = mpsc::Sender<CSig>;
 
-static mut SIGNAL_RECEIVER: Option<Arc<Recv>> = None;
-static mut SIGNAL_SENDER: Option<Send> = None;
+impl Signal {
+    pub fn raw(self) -> CSig {
+        match self {
+            Signal::SIGALRM => c::SIGALRM,
+            Signal::SIGINT => c::SIGINT,
+            Signal::SIGTERM => c::SIGTERM,
+            Signal::SIGUSR1 => c::SIGUSR1,
+            Signal::SIGUSR2 => c::SIGUSR2,
+            Signal::OTHER(c_sig) => c_sig,
+        }
+    }
+}
 
-unsafe fn ensure() -> (&'static Arc<Recv>, &'static Send) {
-    if SIGNAL_RECEIVER.is_none() || SIGNAL_SENDER.is_none() {
-        let (sender, receiver) = mpsc::channel();
-        SIGNAL_RECEIVER = Some(Arc::new(receiver));
-        SIGNAL_SENDER = Some(sender)
+impl From<CSig> for Signal {
+    fn from(c_sig: CSig) -> Self {
+        match c_sig {
+            c::SIGTERM => Signal::SIGTERM,
+            c::SIGINT => Signal::SIGINT,
+            c::SIGALRM => Signal::SIGALRM,
+            c::SIGUSR1 => Signal::SIGUSR1,
+            c::SIGUSR2 => Signal::SIGUSR2,
+            _ => Signal::OTHER(c_sig),
+        }
     }
+}
+
+/// Write end of the self-pipe, -1 until it is created. The C handler only reads it.
+static PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
 
-    (
-        SIGNAL_RECEIVER.as_ref().unwrap(),
-        SIGNAL_SENDER.as_ref().unwrap(),
-    )
+/// Receives signals, and the signals it wants.
+struct Subscriber {
+    signals: Vec<CSig>,
+    sender: mpsc::Sender<Signal>,
 }
 
-fn signal_callback(sig: CSig) {
-    unsafe {
-        if let Err(e) = ensure().1.send(sig) {
-            panic!("signal -> cannot send sig {:?}: {:?}", sig, e);
+struct Dispatch {
+    subscribers: Mutex<Vec<Subscriber>>,
+    /// signals with a handler installed.
+    installed: Mutex<Vec<CSig>>,
+    /// receives what register asked for, for recv.
+    default: Mutex<mpsc::Receiver<Signal>>,
+}
+
+static DISPATCH: OnceLock<io::Result<Dispatch>> = OnceLock::new();
+
+/// Create the self-pipe and the thread reading it, once.
+fn dispatch() -> io::Result<&'static Dispatch> {
+    DISPATCH
+        .get_or_init(|| {
+            let mut fds = [0; 2];
+            if unsafe { c::pipe(fds.as_mut_ptr()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            for &fd in fds.iter() {
+                if unsafe { c::fcntl(fd, c::F_SETFD, c::FD_CLOEXEC) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            // only the write end must not block: the C handler drops signals rather
+            // than wait for a full pipe.
+            if unsafe { c::fcntl(fds[1], c::F_SETFL, c::O_NONBLOCK) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            PIPE_WRITE.store(fds[1], Ordering::SeqCst);
+
+            let (sender, receiver) = mpsc::channel();
+            thread::Builder::new()
+                .name("signal".to_string())
+                .spawn(move || read_pipe(fds[0]))?;
+            Ok(Dispatch {
+                subscribers: Mutex::new(vec![Subscriber {
+                    signals: vec![],
+                    sender,
+                }]),
+                installed: Mutex::new(vec![]),
+                default: Mutex::new(receiver),
+            })
+        })
+        .as_ref()
+        .map_err(|e| io::Error::new(e.kind(), e.to_string()))
+}
+
+/// Send signals written to the pipe to their subscribers, forever.
+fn read_pipe(fd: c::c_int) {
+    let mut buf = [0u8; 64];
+    loop {
+        let n = unsafe { c::read(fd, buf.as_mut_ptr() as *mut c::c_void, buf.len()) };
+        if n < 0 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return;
+        }
+        if n == 0 {
+            return;
+        }
+        let dispatch = match DISPATCH.get() {
+            Some(Ok(dispatch)) => dispatch,
+            _ => continue, // signals received during the initialization.
+        };
+        let mut subscribers = dispatch.subscribers.lock().unwrap();
+        for &byte in buf[..n as usize].iter() {
+            let c_sig = byte as CSig;
+            subscribers.retain(|s| {
+                !s.signals.contains(&c_sig)
+                    || s.sender.send(Signal::from(c_sig)).is_ok()
+            });
         }
     }
 }
 
-pub fn recv() -> Option<Signal> {
+#[cfg(target_os = "linux")]
+unsafe fn errno() -> *mut c::c_int {
+    c::__errno_location()
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn errno() -> *mut c::c_int {
+    c::__error()
+}
+
+/// The C handler: async-signal-safe calls only.
+extern "C" fn signal_callback(c_sig: c::c_int) {
+    let fd = PIPE_WRITE.load(Ordering::Relaxed);
+    if fd < 0 {
+        return;
+    }
+    unsafe {
+        // write may change errno, the interrupted code expects it unchanged.
+        let saved = *errno();
+        let byte = c_sig as u8;
+        c::write(fd, &byte as *const u8 as *const c::c_void, 1);
+        *errno() = saved;
+    }
+}
+
+/// Install the handler of c_sig, once.
+fn install(dispatch: &Dispatch, c_sig: CSig) -> io::Result<()> {
+    let mut installed = dispatch.installed.lock().unwrap();
+    if installed.contains(&c_sig) {
+        return Ok(());
+    }
+    if !(1..=u8::MAX as CSig).contains(&c_sig) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid signal {}", c_sig),
+        ));
+    }
     unsafe {
-        match ensure().0.recv().ok() {
-            Some(c_sig) => match c_sig {
-                c::SIGTERM => Some(Signal::SIGTERM),
-                c::SIGINT => Some(Signal::SIGINT),
-                c::SIGALRM => Some(Signal::SIGALRM),
-                c::SIGUSR1 => Some(Signal::SIGUSR1),
-                c::SIGUSR2 => Some(Signal::SIGUSR2),
-                _ => Some(Signal::OTHER(c_sig)),
-            },
-            None => None,
+        let mut action: c::sigaction = std::mem::zeroed();
+        action.sa_sigaction = signal_callback as extern "C" fn(c::c_int) as usize;
+        action.sa_flags = c::SA_RESTART;
+        c::sigemptyset(&mut action.sa_mask);
+        if c::sigaction(c_sig, &action, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
         }
     }
+    installed.push(c_sig);
+    Ok(())
+}
+
+/// Receive signals from now on, each subscriber getting its own copy.
+///
+/// ```
+/// use signal_libc::signal::{self, Signal};
+///
+/// let first = signal::subscribe(&[Signal::SIGUSR1]).unwrap();
+/// let second = signal::subscribe(&[Signal::SIGUSR1, Signal::SIGUSR2]).unwrap();
+/// unsafe { libc::raise(libc::SIGUSR1) };
+/// assert_eq!(Ok(Signal::SIGUSR1), first.recv());
+/// assert_eq!(Ok(Signal::SIGUSR1), second.recv());
+/// ```
+pub fn subscribe(signals: &[Signal]) -> io::Result<mpsc::Receiver<Signal>> {
+    let dispatch = dispatch()?;
+    let signals: Vec<CSig> = signals.iter().map(|sig| sig.raw()).collect();
+    for &c_sig in signals.iter() {
+        install(dispatch, c_sig)?;
+    }
+    let (sender, receiver) = mpsc::channel();
+    dispatch
+        .subscribers
+        .lock()
+        .unwrap()
+        .push(Subscriber { signals, sender });
+    Ok(receiver)
 }
 
-unsafe fn unsafe_register(c_sig: CSig) {
-    // https://users.rust-lang.org/t/function-pointers-and-raw-function-pointers/15152/7
-    let ptr = (&(signal_callback as *const fn(CSig)) as *const *const fn(CSig))
-        as *const fn(CSig); // wtf?
-    c_signal(c_sig, *ptr as usize);
+/// Wait for a signal given to register. None if signals cannot be received.
+pub fn recv() -> Option<Signal> {
+    let dispatch = dispatch().ok()?;
+    let default = dispatch.default.lock().unwrap();
+    default.recv().ok()
 }
 
 /// ```
-/// use flobot::signal;
+/// use signal_libc::signal;
 ///
 /// signal::register(signal::Signal::SIGINT);
 /// // issue signal…
 /// // use blocking call signal::recv() in a thread loop for example.
 /// ```
 pub fn register(sig: Signal) {
-    unsafe {
-        match sig {
-            Signal::SIGINT => unsafe_register(c::SIGINT),
-            Signal::SIGTERM => unsafe_register(c::SIGTERM),
-            Signal::SIGALRM => unsafe_register(c::SIGALRM),
-            Signal::SIGUSR1 => unsafe_register(c::SIGUSR1),
-            Signal::SIGUSR2 => unsafe_register(c::SIGUSR2),
-            Signal::OTHER(c_sig) => unsafe_register(c_sig),
-        };
-    };
+    let res = dispatch().and_then(|dispatch| {
+        install(dispatch, sig.raw())?;
+        // the default subscriber comes first and is never dropped.
+        dispatch.subscribers.lock().unwrap()[0]
+            .signals
+            .push(sig.raw());
+        Ok(())
+    });
+    if let Err(e) = res {
+        eprintln!("signal -> cannot register {:?}: {:?}", sig, e);
+    }
 }